@@ -1,4 +1,4 @@
-use crate::utils::{*,positions::*,directions::*,grids::*};
+use crate::utils::{*,positions::*,directions::*,grids::*,polygon::*};
 use array2d::Array2D;
 
 #[derive (Debug, Clone, Copy, PartialEq, Eq)]
@@ -26,6 +26,16 @@ impl Pipe {
         }
     }
 
+    fn from_ends(a: Direction4, b: Direction4) -> Option<Pipe> {
+        [Pipe::NorthEast, Pipe::NorthSouth, Pipe::NorthWest,
+         Pipe::EastSouth, Pipe::EastWest, Pipe::SouthWest]
+            .into_iter()
+            .find(|pipe| {
+                let ends = pipe.ends().unwrap();
+                ends == [a, b] || ends == [b, a]
+            })
+    }
+
     fn other_end(self, d: Direction4) -> Option<Direction4> {
         if let Some(ends) = self.ends() {
             if ends[0] == d {
@@ -62,6 +72,32 @@ impl ConvertibleToChar for Pipe {
 type Position = positions::Position<usize>;
 type Grid = grids::Grid<Pipe>;
 
+// The pipe map, where the start tile has been replaced by the pipe under it
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub struct Maze {
+    grid: Grid,
+    pub start: Position,
+    pub start_pipe: Pipe
+}
+
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub enum StartError {
+    NoStart,
+    SeveralStarts,
+    NoLoop,
+    // Every pipe shape that would close a loop at the start
+    Ambiguous(Vec<Pipe>)
+}
+
+// How enclosed tiles are counted
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    // Flood fill from the outside, squeezing between pipes
+    FloodFill,
+    // Shoelace area of the loop and Pick's theorem
+    Pick
+}
+
 #[derive (Debug, Clone, Copy, PartialEq, Eq)]
 enum Enclosure { Inside, Outside, Frontier, NotComputed }
 
@@ -116,15 +152,19 @@ mod parser {
     }
 }
 
-fn find_start(grid: &Grid) -> Position {
+fn find_start(grid: &Grid) -> Result<Position, StartError> {
+    let mut start = None;
     for (i,row_it) in grid.0.rows_iter().enumerate() {
         for (j,&p) in row_it.enumerate() {
             if p == Pipe::Start {
-                return Position::new(i,j)
+                if start.is_some() {
+                    return Err (StartError::SeveralStarts)
+                }
+                start = Some (Position::new(i,j));
             }
         }
     }
-    panic!()
+    start.ok_or(StartError::NoStart)
 }
 
 fn follow_pipe(grid: &Grid, d: Direction4, p: Position)
@@ -135,6 +175,49 @@ fn follow_pipe(grid: &Grid, d: Direction4, p: Position)
     Some ((d, p))
 }
 
+// Leaves the start in direction d and follows the pipes; returns the
+// direction in which the start is entered again, if ever
+fn return_direction(grid: &Grid, mut d: Direction4, start: Position) -> Option<Direction4> {
+    let mut p = start.step(d)?;
+    if !grid.is_index_valid(&p) {
+        return None
+    }
+    while p != start {
+        (d, p) = follow_pipe(grid, d, p)?;
+        if !grid.is_index_valid(&p) {
+            return None
+        }
+    }
+    Some (d)
+}
+
+// The pipes under the start which are part of a loop
+fn start_candidates(grid: &Grid, start: Position) -> Vec<Pipe> {
+    let mut candidates = Vec::new();
+    for d in Direction4::ALL {
+        if let Some(e) = return_direction(grid, d, start) {
+            if let Some(pipe) = Pipe::from_ends(d, e.invert()) {
+                if !candidates.contains(&pipe) {
+                    candidates.push(pipe);
+                }
+            }
+        }
+    }
+    candidates
+}
+
+pub fn load(input: &str) -> Result<Maze, StartError> {
+    let (_,mut grid) = parser::parse(input).unwrap();
+    let start = find_start(&grid)?;
+    let start_pipe = match start_candidates(&grid, start)[..] {
+        [] => return Err (StartError::NoLoop),
+        [pipe] => pipe,
+        ref candidates => return Err (StartError::Ambiguous(candidates.to_vec()))
+    };
+    grid[start] = start_pipe;
+    Ok (Maze { grid, start, start_pipe })
+}
+
 fn cycle_length(grid: &Grid, mut d: Direction4, mut p: Position) -> Option<u32> {
     let mut length = 1;
     let starting_position = p;
@@ -168,30 +251,23 @@ fn mark_cycle(grid: &Grid, mut d: Direction4, mut p: Position)
     }
 }
 
-fn solve_part1(grid: &Grid) -> u32 {
-    let p = find_start(grid);
-    for d in Direction4::ALL {
-        if let Some(l) = cycle_length(grid, d, p) {
-            return l / 2;
-        }
+impl Maze {
+    fn start_direction(&self) -> Direction4 {
+        self.start_pipe.ends().unwrap()[0]
     }
-
-    panic!();
 }
 
-fn mark_loop(grid: &Grid) -> Array2D<bool> {
-    let p = find_start(grid);
-    for d in Direction4::ALL {
-        if let Some(marks) = mark_cycle(grid, d, p) {
-            return marks;
-        }
-    }
+fn solve_part1(maze: &Maze) -> u32 {
+    cycle_length(&maze.grid, maze.start_direction(), maze.start).unwrap() / 2
+}
 
-    panic!()
+fn mark_loop(maze: &Maze) -> Array2D<bool> {
+    mark_cycle(&maze.grid, maze.start_direction(), maze.start).unwrap()
 }
 
-fn compute_enclosure(grid: &Grid) -> Array2D<Enclosure> {
-    let marks = mark_loop(grid);
+fn compute_enclosure(maze: &Maze) -> Array2D<Enclosure> {
+    let grid = &maze.grid;
+    let marks = mark_loop(maze);
     let mut stack = vec![
         (Direction8::North, Position(0, 0), Enclosure::Outside)
     ];
@@ -272,20 +348,47 @@ fn count_inner_tiles(enclosure: &Array2D<Enclosure>) -> u32 {
     count
 }
 
-fn solve_part2(grid: &Grid) -> u32 {
-    let enclosure = compute_enclosure(grid);
-    _print_enclosure(grid, &enclosure);
-    count_inner_tiles(&enclosure)
+// Tiles of the loop, in order, starting at the start tile
+fn loop_positions(maze: &Maze) -> Vec<Position> {
+    let mut d = maze.start_direction();
+    let mut p = maze.start.step(d).unwrap();
+    let mut positions = vec![maze.start];
+
+    while p != maze.start {
+        positions.push(p);
+        (d, p) = follow_pipe(&maze.grid, d, p).unwrap();
+    }
+
+    positions
+}
+
+fn count_lattice_points(maze: &Maze) -> u32 {
+    Polygon(loop_positions(maze)).interior_points() as u32
+}
+
+fn solve_part2(maze: &Maze, strategy: Strategy) -> u32 {
+    match strategy {
+        Strategy::FloodFill => {
+            let enclosure = compute_enclosure(maze);
+            _print_enclosure(&maze.grid, &enclosure);
+            count_inner_tiles(&enclosure)
+        },
+        Strategy::Pick => count_lattice_points(maze)
+    }
 }
 
 pub fn solve(input: &str) -> (u32, u32) {
-    let (_,grid) = parser::parse(input).unwrap();
-    (solve_part1(&grid), solve_part2(&grid))
+    let maze = load(input).unwrap();
+    (solve_part1(&maze), solve_part2(&maze, Strategy::FloodFill))
 }
 
 pub fn solve2(input: &str) -> u32 {
-    let (_,grid) = parser::parse(input).unwrap();
-    solve_part2(&grid)
+    solve2_with(input, Strategy::FloodFill)
+}
+
+pub fn solve2_with(input: &str, strategy: Strategy) -> u32 {
+    let maze = load(input).unwrap();
+    solve_part2(&maze, strategy)
 }
 
 #[test]
@@ -323,3 +426,45 @@ fn day10_input() {
   let solution = solve(&include_str!("../inputs/day10-input"));
   assert_eq!(solution, (6682, 353));
 }
+
+#[test]
+fn day10_start_inference() {
+    let examples = [
+        (include_str!("../inputs/day10-example1"), Pipe::EastSouth),
+        (include_str!("../inputs/day10-example2"), Pipe::EastSouth),
+        (include_str!("../inputs/day10-example4"), Pipe::EastSouth),
+        (include_str!("../inputs/day10-example5"), Pipe::SouthWest)
+    ];
+    for (input, pipe) in examples {
+        let maze = load(input).unwrap();
+        assert_eq!(maze.start_pipe, pipe);
+        assert_eq!(maze.grid[maze.start], pipe);
+    }
+
+    let maze = load(".S-7\n.|.|\n.L-J").unwrap();
+    assert_eq!(maze.start, Position(0, 1));
+    assert_eq!(maze.grid, parser::parse(".F-7\n.|.|\n.L-J").unwrap().1);
+
+    assert_eq!(load("..\n.."), Err (StartError::NoStart));
+    assert_eq!(load("S.\n.S"), Err (StartError::SeveralStarts));
+    assert_eq!(load("S-\n.."), Err (StartError::NoLoop));
+    // Two loops go through the start
+    assert_eq!(load("F7...\n|L7..\nL-S-7\n..|.|\n..L-J"),
+        Err (StartError::Ambiguous(vec![Pipe::NorthWest, Pipe::EastSouth])));
+}
+
+#[test]
+fn day10_strategies() {
+    let inputs = [
+        include_str!("../inputs/day10-example1"),
+        include_str!("../inputs/day10-example2"),
+        include_str!("../inputs/day10-example3"),
+        include_str!("../inputs/day10-example4"),
+        include_str!("../inputs/day10-example5"),
+        include_str!("../inputs/day10-input")
+    ];
+    for input in inputs {
+        assert_eq!(solve2_with(input, Strategy::Pick), solve2_with(input, Strategy::FloodFill));
+    }
+    assert_eq!(solve2_with(inputs[4], Strategy::Pick), 10);
+}
@@ -1,4 +1,5 @@
-use crate::utils::{directions::*, positions::*};
+use std::fmt;
+use crate::utils::{directions::*, positions::*, polygon::*, grids::*};
 
 #[derive (Debug)]
 pub struct Order {
@@ -64,67 +65,244 @@ impl SimpleOrder {
         Self { direction: order.direction, distance: order.distance }
     }
 
-    fn fix(order: &Order) -> Self {
+    fn fix(order: &Order) -> Option<Self> {
         let direction = match order.color & 0xf {
                 0 => Direction4::East,
                 1 => Direction4::South,
                 2 => Direction4::West,
                 3 => Direction4::North,
-                _ => panic!()
+                _ => return None
             };
         let distance = order.color / 16;
-        Self { direction, distance }
+        Some (Self { direction, distance })
     }
 }
 
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub enum PlanError {
+    // Order whose colour doesn't encode a direction
+    InvalidDirection(usize),
+    // Order going back along the previous one
+    Reversal(usize),
+    // The path ends there instead of returning to the origin
+    NotClosed(Position),
+    // Orders whose segments overlap or cross
+    SelfIntersection(usize, usize)
+}
+
+fn fix_plan(orders: &[Order]) -> Result<Vec<SimpleOrder>, PlanError> {
+    orders.iter()
+        .enumerate()
+        .map(|(i, order)| SimpleOrder::fix(order).ok_or(PlanError::InvalidDirection(i)))
+        .collect()
+}
+
 
 type Position = crate::utils::positions::Position<i32>;
 
-fn walk(orders: &Vec<SimpleOrder>) -> Vec<Position> {
+fn advance(mut p: Position, order: &SimpleOrder) -> Position {
+    for _ in 0..order.distance {
+        p = p.step(order.direction).unwrap();
+    }
+    p
+}
+
+fn walk(orders: &[SimpleOrder]) -> Vec<Position> {
     let mut p = Position(0, 0);
     let mut v = Vec::new();
 
     for order in orders {
         v.push(p);
-        for _ in 0..order.distance {
-            p = p.step(order.direction).unwrap();
-        }
+        p = advance(p, order);
     }
 
     v
 }
 
-fn area_inside_curve(curve: &Vec<Position>, with_perimeter: bool) -> u64 {
-    let mut double_area: i64 = 0;
-    let mut perimeter: i64 = 0;
+// The vertices of the loop, provided the orders describe a simple closed loop
+fn checked_walk(orders: &[SimpleOrder]) -> Result<Vec<Position>, PlanError> {
+    // The first order follows the last one, as the path is a loop
+    let n = orders.len();
+    for i in 1..=n {
+        if orders[i % n].direction == orders[i - 1].direction.invert() {
+            return Err (PlanError::Reversal(i % n))
+        }
+    }
 
-    for i in 0..curve.len() {
-        let j = if i + 1 >= curve.len() {
-                i + 1 - curve.len()
-            } else {
-                i + 1
-            };
-        double_area +=
-            (curve[i].0 as i64 - curve[j].0 as i64) *
-            (curve[i].1 as i64 + curve[j].1 as i64);
-        perimeter +=
-            (curve[i].0 as i64 - curve[j].0 as i64).abs() +
-            (curve[i].1 as i64 - curve[j].1 as i64).abs();
+    let curve = walk(orders);
+    let end = advance(*curve.last().unwrap(), orders.last().unwrap());
+    if end != Position(0, 0) {
+        return Err (PlanError::NotClosed(end))
     }
 
-    let pc = if with_perimeter { 1 } else { -1 };
-    let real_area = (double_area.abs() + pc * perimeter) / 2 + 1;
-    real_area as u64
+    // Edge i of the polygon is dug by order i
+    match Polygon(curve.clone()).self_intersection() {
+        Some((i, j)) => Err (PlanError::SelfIntersection(i, j)),
+        None => Ok (curve)
+    }
 }
 
-pub fn solve(input: &str) -> (u64, u64) {
+// Number of tiles dug, i.e. lattice points inside or on the curve
+fn area_inside_curve(curve: &[Position]) -> u64 {
+    Polygon(curve.to_vec()).lattice_points() as u64
+}
+
+
+// --- Rasterisation ---
+
+#[derive (Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Tile { #[default] Unknown, Trench, Lagoon, Outside }
+
+impl ConvertibleToChar for Tile {
+    fn to_char(&self) -> char {
+        match self {
+            Tile::Unknown => '?',
+            Tile::Trench => '#',
+            Tile::Lagoon => '~',
+            Tile::Outside => '.'
+        }
+    }
+}
+
+// Compresses the coordinates of one axis: cell k covers [b_k, b_k+1) where b
+// are the returned boundaries. Every vertex coordinate gets its own cell, the
+// gaps between them are merged and a margin is left on both sides.
+fn compress(values: impl Iterator<Item = i32>) -> Vec<i32> {
+    let mut boundaries: Vec<i32> = values.flat_map(|v| [v, v + 1]).collect();
+    boundaries.sort();
+    boundaries.dedup();
+    let (min, max) = (boundaries[0], boundaries[boundaries.len() - 1]);
+    boundaries.insert(0, min - 1);
+    boundaries.push(max + 1);
+    boundaries
+}
+
+fn cell(boundaries: &[i32], v: i32) -> usize {
+    boundaries.binary_search(&v).unwrap()
+}
+
+// The dig plan drawn on a grid of compressed coordinates
+pub struct Raster {
+    rows: Vec<i32>,
+    columns: Vec<i32>,
+    grid: Grid<Tile>
+}
+
+impl Raster {
+    pub fn new(curve: &[Position]) -> Self {
+        let rows = compress(curve.iter().map(|p| p.0));
+        let columns = compress(curve.iter().map(|p| p.1));
+        let mut grid = Grid::filled_default(rows.len() - 1, columns.len() - 1);
+
+        let next = curve.iter().cycle().skip(1);
+        for (p, q) in curve.iter().zip(next) {
+            let (i1, i2) = (cell(&rows, p.0), cell(&rows, q.0));
+            let (j1, j2) = (cell(&columns, p.1), cell(&columns, q.1));
+            for i in i1.min(i2)..=i1.max(i2) {
+                for j in j1.min(j2)..=j1.max(j2) {
+                    grid[Position(i, j)] = Tile::Trench;
+                }
+            }
+        }
+
+        // The margin is outside, everything not reachable from it is dug out
+        let mut stack = vec![Position(0usize, 0usize)];
+        while let Some(p) = stack.pop() {
+            if grid[p] != Tile::Unknown {
+                continue
+            }
+            grid[p] = Tile::Outside;
+            for d in Direction4::ALL {
+                if let Some(q) = p.step(d) {
+                    if grid.is_index_valid(&q) {
+                        stack.push(q);
+                    }
+                }
+            }
+        }
+        for i in 0..grid.0.num_rows() {
+            for j in 0..grid.0.num_columns() {
+                if grid[Position(i, j)] == Tile::Unknown {
+                    grid[Position(i, j)] = Tile::Lagoon;
+                }
+            }
+        }
+
+        Raster { rows, columns, grid }
+    }
+
+    // Sum of the real sizes of the dug cells
+    pub fn area(&self) -> u64 {
+        let mut area = 0;
+        for (i, row_it) in self.grid.0.rows_iter().enumerate() {
+            for (j, &tile) in row_it.enumerate() {
+                if tile == Tile::Trench || tile == Tile::Lagoon {
+                    let height = self.rows[i + 1] - self.rows[i];
+                    let width = self.columns[j + 1] - self.columns[j];
+                    area += height as u64 * width as u64;
+                }
+            }
+        }
+        area
+    }
+}
+
+impl fmt::Display for Raster {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.grid)
+    }
+}
+
+// --- Rendering ---
+
+// SVG drawing of the trench, each edge in the colour of its order, with the
+// interior optionally filled with the given colour
+pub fn render_svg(orders: &[Order], fill: Option<u32>) -> String {
+    let simple_orders: Vec<SimpleOrder> = orders.iter().map(SimpleOrder::from).collect();
+    let curve = walk(&simple_orders);
+    let min_x = curve.iter().map(|p| p.1).min().unwrap();
+    let max_x = curve.iter().map(|p| p.1).max().unwrap();
+    let min_y = curve.iter().map(|p| p.0).min().unwrap();
+    let max_y = curve.iter().map(|p| p.0).max().unwrap();
+
+    // Tiles are unit squares centered on their coordinates
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">\n",
+        min_x as f64 - 0.5, min_y as f64 - 0.5, max_x - min_x + 1, max_y - min_y + 1);
+    if let Some(color) = fill {
+        let points: Vec<String> = curve.iter().map(|p| format!("{},{}", p.1, p.0)).collect();
+        svg += &format!("<polygon points=\"{}\" fill=\"#{color:06x}\"/>\n", points.join(" "));
+    }
+    let next = curve.iter().cycle().skip(1);
+    for ((p, q), order) in curve.iter().zip(next).zip(orders) {
+        svg += &format!(
+            "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"#{:06x}\" \
+            stroke-width=\"1\" stroke-linecap=\"square\"/>\n",
+            p.1, p.0, q.1, q.0, order.color);
+    }
+    svg += "</svg>\n";
+    svg
+}
+
+fn lagoon_size(orders: &[SimpleOrder]) -> Result<u64, PlanError> {
+    let curve = checked_walk(orders)?;
+    Ok (area_inside_curve(&curve))
+}
+
+// The plans of both parts are validated separately: the plan as written and
+// the one decoded from the colours
+pub fn try_solve(input: &str) -> (Result<u64, PlanError>, Result<u64, PlanError>) {
     let (_,orders) = parser::parse(input).unwrap();
-    let orders1 = orders.iter().map(SimpleOrder::from).collect();
-    let orders2 = orders.iter().map(SimpleOrder::fix).collect();
-    let curve1 = walk(&orders1);
-    let curve2 = walk(&orders2);
+    let orders1: Vec<SimpleOrder> = orders.iter().map(SimpleOrder::from).collect();
+    let part1 = lagoon_size(&orders1);
+    let part2 = fix_plan(&orders).and_then(|orders2| lagoon_size(&orders2));
+
+    (part1, part2)
+}
 
-    (area_inside_curve(&curve1, true), area_inside_curve(&curve2, true))
+pub fn solve(input: &str) -> (u64, u64) {
+    let (part1, part2) = try_solve(input);
+    (part1.unwrap(), part2.unwrap())
 }
 
 #[test]
@@ -138,3 +316,92 @@ fn day18_input() {
     let solution = solve(include_str!("../inputs/day18-input"));
     assert_eq!(solution, (34329, 42617947302920));
 }
+
+#[test]
+fn day18_polygon() {
+    let (_,orders) = parser::parse(include_str!("../inputs/day18-input")).unwrap();
+    for orders in [
+            orders.iter().map(SimpleOrder::from).collect(),
+            fix_plan(&orders).unwrap()] {
+        let polygon = Polygon(walk(&orders));
+        assert!(polygon.is_simple());
+        assert_ne!(polygon.orientation(), Orientation::Degenerate);
+        assert_eq!(polygon.perimeter() as u128, polygon.boundary_points());
+        assert!(polygon.contains(&Position(0, 0)));
+    }
+}
+
+#[test]
+fn day18_raster() {
+    let (_,orders) = parser::parse(include_str!("../inputs/day18-example")).unwrap();
+    let orders: Vec<SimpleOrder> = orders.iter().map(SimpleOrder::from).collect();
+    let raster = Raster::new(&walk(&orders));
+    let expected = [
+        ".........",
+        ".#######.",
+        ".#~~~~~#.",
+        ".###~~~#.",
+        "...#~~~#.",
+        ".###~###.",
+        ".#~~~#...",
+        ".##~~###.",
+        "..#~~~~#.",
+        "..######.",
+        ".........",
+    ];
+    assert_eq!(raster.to_string(), expected.map(|line| line.to_owned() + "\n").concat());
+
+    for input in [include_str!("../inputs/day18-example"), include_str!("../inputs/day18-input")] {
+        let (_,orders) = parser::parse(input).unwrap();
+        for orders in [
+                orders.iter().map(SimpleOrder::from).collect(),
+                fix_plan(&orders).unwrap()] {
+            let curve = walk(&orders);
+            assert_eq!(Raster::new(&curve).area(), area_inside_curve(&curve));
+        }
+    }
+}
+
+#[test]
+fn day18_render() {
+    let (_,orders) = parser::parse(include_str!("../inputs/day18-example")).unwrap();
+    let svg = render_svg(&orders, None);
+    assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"-0.5 -0.5 7 10\">\n"));
+    assert_eq!(svg.matches("<line ").count(), orders.len());
+    assert!(svg.contains("<line x1=\"0\" y1=\"0\" x2=\"6\" y2=\"0\" stroke=\"#70c710\""));
+    assert!(svg.contains("<line x1=\"6\" y1=\"0\" x2=\"6\" y2=\"5\" stroke=\"#0dc571\""));
+    assert!(!svg.contains("<polygon"));
+
+    let svg = render_svg(&orders, Some(0x3060c0));
+    assert!(svg.contains("<polygon points=\"0,0 6,0 6,5 4,5 4,7 6,7 6,9 1,9 1,7 0,7 0,5 2,5 2,2 0,2\" \
+        fill=\"#3060c0\"/>"));
+    assert!(svg.find("<polygon") < svg.find("<line"));
+}
+
+#[test]
+fn day18_validation() {
+    let plan = |orders: &[&str]| try_solve(&orders.join("\n"));
+    let square = Ok (9);
+    assert_eq!(plan(&["R 2 (#000020)", "D 2 (#000021)", "L 2 (#000022)", "U 2 (#000023)"]),
+        (square.clone(), square.clone()));
+    assert_eq!(plan(&["R 2 (#000020)", "D 2 (#000021)", "L 2 (#000022)", "U 2 (#000024)"]),
+        (square.clone(), Err (PlanError::InvalidDirection(3))));
+    assert_eq!(plan(&["R 2 (#000020)", "D 2 (#000021)", "L 2 (#000022)", "U 1 (#000023)"]),
+        (Err (PlanError::NotClosed(Position(1, 0))), square.clone()));
+    assert_eq!(plan(&["R 2 (#000020)", "L 1 (#000021)", "D 2 (#000022)", "U 2 (#000023)"]),
+        (Err (PlanError::Reversal(1)), square.clone()));
+    assert_eq!(plan(&["R 2 (#000020)", "D 2 (#000021)", "L 2 (#000022)", "U 2 (#000021)"]),
+        (square.clone(), Err (PlanError::NotClosed(Position(4, 0)))));
+    // The last order comes back along the first one
+    assert_eq!(plan(&["R 3 (#000020)", "D 2 (#000021)", "L 1 (#000022)", "U 2 (#000023)",
+            "L 2 (#000020)"]).0,
+        Err (PlanError::Reversal(0)));
+    // A figure eight
+    assert_eq!(plan(&["R 2 (#000020)", "D 4 (#000021)", "R 2 (#000022)", "U 2 (#000023)",
+            "L 4 (#000020)", "U 2 (#000021)"]).0,
+        Err (PlanError::SelfIntersection(1, 4)));
+    // Going twice through the same corner
+    assert_eq!(plan(&["R 2 (#000020)", "D 2 (#000021)", "R 2 (#000022)", "D 2 (#000023)",
+            "L 2 (#000020)", "U 2 (#000021)", "L 2 (#000020)", "U 2 (#000021)"]).0,
+        Err (PlanError::SelfIntersection(1, 5)));
+}
@@ -1,118 +1,149 @@
-use std::{ops::{Index,IndexMut}, collections::HashMap};
-use crate::utils::intervals::*;
+use std::{fmt, ops::{Index,IndexMut}, collections::{HashMap, HashSet}};
+use crate::utils::{intervals::*, boxes::*};
 
 // --- Types definitions ---
 
-#[derive (Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Variable {X, M, A, S}
+#[derive (Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Variable(usize);
 type WorkflowName = String;
 type Value = u32;
 
+// Names of the variables, in the order ratings list them, followed by those
+// only appearing in workflows
+#[derive (Debug, Clone, Default, PartialEq, Eq)]
+pub struct Variables(Vec<String>);
+
+impl Variables {
+    fn intern(&mut self, name: &str) -> Variable {
+        match self.get(name) {
+            Some(variable) => variable,
+            None => {
+                self.0.push(String::from(name));
+                Variable(self.0.len() - 1)
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Variable> {
+        self.0.iter().position(|n| n == name).map(Variable)
+    }
+
+    pub fn name(&self, variable: Variable) -> &str {
+        &self.0[variable.0]
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
 #[derive (Debug, Clone, PartialEq, Eq)]
 pub enum Condition {
     Lower(Variable, Value),
-    Greater(Variable, Value)
+    LowerOrEqual(Variable, Value),
+    Greater(Variable, Value),
+    GreaterOrEqual(Variable, Value),
+    Equal(Variable, Value),
+    Different(Variable, Value),
+    And(Vec<Condition>)
 }
 
 #[derive (Debug, Clone, PartialEq, Eq)]
 pub enum Action { Accept, Reject, Follow(WorkflowName) }
 
-#[derive (Debug, Clone)]
+#[derive (Debug, Clone, PartialEq, Eq)]
 pub struct Rule {
     condition: Condition,
     action: Action
 }
 
-#[derive (Debug, Clone)]
+#[derive (Debug, Clone, PartialEq, Eq)]
 pub struct Workflow {
     name: WorkflowName,
     rules: Vec<Rule>,
     default: Action
 }
 
-#[derive (Debug, Clone, PartialEq, Eq)]
-pub struct Valuation<T> {
-    x: T,
-    m: T,
-    a: T,
-    s: T
-}
-
-impl<T: Default> Default for Valuation<T> {
-    fn default() -> Self {
-        Valuation {
-            x: T::default(),
-            m: T::default(),
-            a: T::default(),
-            s: T::default()
-        }
-    }
-}
+// One value per variable, indexed by the variable number
+#[derive (Debug, Clone, Default, PartialEq, Eq)]
+pub struct Valuation<T> (pub Vec<T>);
 
 impl<T> Index<Variable> for Valuation<T> {
     type Output = T;
 
     fn index(&self, index: Variable) -> &T {
-        match index {
-            Variable::X => &self.x,
-            Variable::M => &self.m,
-            Variable::A => &self.a,
-            Variable::S => &self.s
-        }
+        &self.0[index.0]
     }
 }
 
 impl<T> IndexMut<Variable> for Valuation<T> {
     fn index_mut(&mut self, index: Variable) -> &mut T {
-        match index {
-            Variable::X => &mut self.x,
-            Variable::M => &mut self.m,
-            Variable::A => &mut self.a,
-            Variable::S => &mut self.s
-        }
+        &mut self.0[index.0]
     }
 }
 
 type Rating  = Valuation<Value>;
-type Input = (Vec<Workflow>, Vec<Rating>);
+type Input = (Variables, Vec<Workflow>, Vec<Rating>);
 
 
 // --- Parser ---
 
 mod parser {
+    use std::cell::RefCell;
     use nom::{
         IResult,
         character::complete::*,
         combinator::*,
         sequence::*,
-        multi::*
+        multi::*,
+        branch::*,
+        bytes::complete::tag
     }; 
 
     use super::*;
 
+    // Variables are numbered as they are discovered
+    type VariablesCell = RefCell<Variables>;
+
     fn value(input: &str) -> IResult<&str, Value> {
         u32(input)
     }
 
-    fn variable(input: &str) -> IResult<&str, Variable> {
-        let (input, c) = one_of("xmas")(input)?;
-        let d = match c {
-                'x' => Variable::X,
-                'm' => Variable::M,
-                'a' => Variable::A,
-                's' => Variable::S,
+    fn operator(input: &str) -> IResult<&str, &str> {
+        alt((tag("<="), tag(">="), tag("=="), tag("!="), tag("<"), tag(">")))(input)
+    }
+
+    fn comparison<'a>(variables: &VariablesCell, input: &'a str)
+            -> IResult<&'a str, Condition> {
+        let (input, (name, op, i)) =
+            tuple((alpha1, operator, value))(input)?;
+        let v = variables.borrow_mut().intern(name);
+        let condition = match op {
+                "<" => Condition::Lower(v, i),
+                "<=" => Condition::LowerOrEqual(v, i),
+                ">" => Condition::Greater(v, i),
+                ">=" => Condition::GreaterOrEqual(v, i),
+                "==" => Condition::Equal(v, i),
+                "!=" => Condition::Different(v, i),
                 _ => panic!()
             };
-        Ok ((input, d))
+        Ok ((input, condition))
     }
 
-    fn condition(input: &str) -> IResult<&str, Condition> {
-        let (input, (v, c, i)) =
-            tuple((variable, one_of("<>"), value))(input)?;
-        let condition = match c {
-                '<' => Condition::Lower(v, i),
-                '>' => Condition::Greater(v, i),
-                _ => panic!()
+    fn condition<'a>(variables: &VariablesCell, input: &'a str)
+            -> IResult<&'a str, Condition> {
+        let (input, mut conditions) =
+            separated_list1(char('&'), |i| comparison(variables, i))(input)?;
+        let condition =
+            if conditions.len() == 1 {
+                conditions.pop().unwrap()
+            }
+            else {
+                Condition::And(conditions)
             };
         Ok ((input, condition))
     }
@@ -125,48 +156,260 @@ mod parser {
         })(input)
     }
 
-    fn rule(input: &str) -> IResult<&str, Rule> {
+    fn rule<'a>(variables: &VariablesCell, input: &'a str) -> IResult<&'a str, Rule> {
         let (input, (condition, action)) =
-            separated_pair(condition, char(':'), action)(input)?;
+            separated_pair(|i| condition(variables, i), char(':'), action)(input)?;
         Ok ((input, Rule { condition, action }))
     }
 
-    fn workflow(input: &str) -> IResult<&str, Workflow> {
+    fn workflow<'a>(variables: &VariablesCell, input: &'a str)
+            -> IResult<&'a str, Workflow> {
         let (input, (name, (rules, default))) =
             pair(
                 alpha1,
                 delimited(
                     char('{'),
                     separated_pair(
-                        separated_list0(char(','), rule),
+                        separated_list0(char(','), |i| rule(variables, i)),
                         char(','),
                         action),
                     char('}')))(input)?;
         Ok((input, Workflow { name: String::from(name), rules, default }))
     }
 
-    fn initialization(input: &str) -> IResult<&str, (Variable, Value)> {
-        separated_pair(variable, char('='), value)(input)
+    fn initialization<'a>(variables: &VariablesCell, input: &'a str)
+            -> IResult<&'a str, (Variable, Value)> {
+        let (input, (name, value)) = separated_pair(alpha1, char('='), value)(input)?;
+        Ok ((input, (variables.borrow_mut().intern(name), value)))
     }
 
-    fn rating(input: &str) -> IResult<&str, Rating> {
-        let (input, v) = delimited(
+    fn rating<'a>(variables: &VariablesCell, input: &'a str)
+            -> IResult<&'a str, Vec<(Variable, Value)>> {
+        delimited(
             char('{'),
-            separated_list1(char(','), initialization),
-            char('}'))(input)?;
-        let mut rating = Valuation::default();
-        for (variable, value) in v {
-            rating[variable] = value;
+            separated_list1(char(','), |i| initialization(variables, i)),
+            char('}'))(input)
+    }
+
+    fn renumber(condition: &mut Condition, renumbering: &[usize]) {
+        match condition {
+            Condition::Lower(v, _) | Condition::LowerOrEqual(v, _) |
+            Condition::Greater(v, _) | Condition::GreaterOrEqual(v, _) |
+            Condition::Equal(v, _) | Condition::Different(v, _) =>
+                v.0 = renumbering[v.0],
+            Condition::And(conditions) =>
+                conditions.iter_mut().for_each(|c| renumber(c, renumbering))
         }
-        Ok((input, rating))
     }
+
     pub fn parse(input: &str) -> IResult<&str, Input> {
-        all_consuming(terminated(
-            separated_pair(
-                separated_list1(multispace1, workflow),
-                multispace1,
-                separated_list1(multispace1, rating)),
-            multispace0))(input)
+        let variables = RefCell::new(Variables::default());
+        let (input, (mut workflows, ratings)) =
+            all_consuming(terminated(
+                separated_pair(
+                    separated_list1(multispace1, |i| workflow(&variables, i)),
+                    multispace1,
+                    separated_list1(multispace1, |i| rating(&variables, i))),
+                multispace0))(input)?;
+
+        // Variables are renumbered in the order ratings list them, so that
+        // printing a rating keeps its fields in place
+        let variables = variables.into_inner();
+        let mut order: Vec<Variable> = Vec::new();
+        let listed = ratings.iter().flatten().map(|&(v, _)| v);
+        for v in listed.chain((0..variables.len()).map(Variable)) {
+            if !order.contains(&v) {
+                order.push(v);
+            }
+        }
+        let mut renumbering = vec![0; order.len()];
+        for (i, v) in order.iter().enumerate() {
+            renumbering[v.0] = i;
+        }
+        for rule in workflows.iter_mut().flat_map(|w| w.rules.iter_mut()) {
+            renumber(&mut rule.condition, &renumbering);
+        }
+
+        // Variables missing from a rating are set to zero
+        let ratings = ratings.into_iter()
+            .map(|fields| {
+                let mut rating = Valuation(vec![0; order.len()]);
+                for (v, value) in fields {
+                    rating[Variable(renumbering[v.0])] = value;
+                }
+                rating
+            })
+            .collect();
+        let variables = Variables(order.iter().map(|&v| String::from(variables.name(v))).collect());
+        Ok ((input, (variables, workflows, ratings)))
+    }
+}
+
+
+// --- Printing ---
+
+// A value along with the variable names needed to print it
+pub struct Named<'a, T> {
+    variables: &'a Variables,
+    value: &'a T
+}
+
+impl Variables {
+    pub fn display<'a, T>(&'a self, value: &'a T) -> Named<'a, T> {
+        Named { variables: self, value }
+    }
+}
+
+impl fmt::Display for Named<'_, Condition> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (var, op, val) = match self.value {
+            Condition::Lower(var, val) => (var, "<", val),
+            Condition::LowerOrEqual(var, val) => (var, "<=", val),
+            Condition::Greater(var, val) => (var, ">", val),
+            Condition::GreaterOrEqual(var, val) => (var, ">=", val),
+            Condition::Equal(var, val) => (var, "==", val),
+            Condition::Different(var, val) => (var, "!=", val),
+            Condition::And(conditions) => {
+                for (i, condition) in conditions.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "&")?;
+                    }
+                    write!(f, "{}", self.variables.display(condition))?;
+                }
+                return Ok (())
+            }
+        };
+        write!(f, "{}{op}{val}", self.variables.name(*var))
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Accept => write!(f, "A"),
+            Action::Reject => write!(f, "R"),
+            Action::Follow(target) => write!(f, "{target}")
+        }
+    }
+}
+
+impl fmt::Display for Named<'_, Workflow> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{{", self.value.name)?;
+        for rule in &self.value.rules {
+            write!(f, "{}:{},", self.variables.display(&rule.condition), rule.action)?;
+        }
+        write!(f, "{}}}", self.value.default)
+    }
+}
+
+impl fmt::Display for Named<'_, Rating> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        for (i, value) in self.value.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}={value}", self.variables.name(Variable(i)))?;
+        }
+        write!(f, "}}")
+    }
+}
+
+// Writes back the puzzle input. Ratings list every variable, in variable
+// order, so the text only matches the original input when all its ratings
+// list the same variables in the same order: missing fields come back as
+// v=0 and fields given in another order are reordered.
+pub fn format_input(variables: &Variables, workflows: &[Workflow], ratings: &[Rating]) -> String {
+    let mut output = String::new();
+    for workflow in workflows {
+        output += &format!("{}\n", variables.display(workflow));
+    }
+    output += "\n";
+    for rating in ratings {
+        output += &format!("{}\n", variables.display(rating));
+    }
+    output
+}
+
+
+// --- Tracing ---
+
+// The rule of a workflow whose action was taken, None designating the
+// fall-through action
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub workflow: WorkflowName,
+    pub rule: Option<usize>
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.rule {
+            Some(rule) => write!(f, "{}:{rule}", self.workflow),
+            None => write!(f, "{}", self.workflow)
+        }
+    }
+}
+
+pub trait Tracer {
+    // A part took the action of the given step
+    fn step(&mut self, _step: &Step) {}
+
+    // A part was finally accepted or rejected
+    fn outcome(&mut self, _accepted: bool) {}
+
+    // A set of parts reaches a rule of a workflow
+    fn split(&mut self, _workflow: &Workflow, _rule_number: usize, _parts: &PartSet) {}
+}
+
+pub struct NoTracer;
+
+impl Tracer for NoTracer {}
+
+pub struct PrintTracer;
+
+impl Tracer for PrintTracer {
+    fn step(&mut self, step: &Step) {
+        println!("{step}");
+    }
+
+    fn outcome(&mut self, accepted: bool) {
+        println!("{}", if accepted { "A" } else { "R" });
+    }
+
+    fn split(&mut self, workflow: &Workflow, rule_number: usize, parts: &PartSet) {
+        println!("{}:{rule_number} / {parts:?}", workflow.name);
+    }
+}
+
+// Records the decision path of a single part
+#[derive (Debug, Clone, Default, PartialEq, Eq)]
+pub struct PathTracer {
+    pub steps: Vec<Step>,
+    pub accepted: Option<bool>
+}
+
+impl Tracer for PathTracer {
+    fn step(&mut self, step: &Step) {
+        self.steps.push(step.clone());
+    }
+
+    fn outcome(&mut self, accepted: bool) {
+        self.accepted = Some (accepted);
+    }
+}
+
+impl fmt::Display for PathTracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in &self.steps {
+            write!(f, "{step} -> ")?;
+        }
+        match self.accepted {
+            Some(true) => write!(f, "A"),
+            Some(false) => write!(f, "R"),
+            None => write!(f, "?")
+        }
     }
 }
 
@@ -174,45 +417,60 @@ mod parser {
 // --- Part 1 ---
 
 fn check_condition(condition: &Condition, part: &Rating) -> bool {
-    match *condition {
-        Condition::Greater(var, val) => part[var] > val,
-        Condition::Lower(var, val) => part[var] < val,
+    match condition {
+        Condition::Lower(var, val) => part[*var] < *val,
+        Condition::LowerOrEqual(var, val) => part[*var] <= *val,
+        Condition::Greater(var, val) => part[*var] > *val,
+        Condition::GreaterOrEqual(var, val) => part[*var] >= *val,
+        Condition::Equal(var, val) => part[*var] == *val,
+        Condition::Different(var, val) => part[*var] != *val,
+        Condition::And(conditions) => conditions.iter().all(|c| check_condition(c, part))
     }
 }
 
 type WorkflowMap<'a> = HashMap<&'a WorkflowName, &'a Workflow>;
 
 
-fn sort_part(workflows: &WorkflowMap, part: &Rating) -> bool {
+fn sort_part(workflows: &WorkflowMap, part: &Rating, tracer: &mut impl Tracer) -> bool {
 
     let mut current_workflow = &String::from("in");
     loop {
         let workflow = *workflows.get(&current_workflow).unwrap();
         let mut matching_action = &workflow.default;
-        for rule in &workflow.rules {
+        let mut matching_rule = None;
+        for (i, rule) in workflow.rules.iter().enumerate() {
             if check_condition(&rule.condition, part) {
                 matching_action = &rule.action;
+                matching_rule = Some (i);
                 break;
             }
         }
 
+        tracer.step(&Step { workflow: workflow.name.clone(), rule: matching_rule });
+
         match matching_action {
-            Action::Accept => return true,
-            Action::Reject => return false,
+            Action::Accept => { tracer.outcome(true); return true },
+            Action::Reject => { tracer.outcome(false); return false },
             Action::Follow(target) => current_workflow = target
         }
     }
 }
 
+pub fn trace_part(workflows: &WorkflowMap, part: &Rating) -> PathTracer {
+    let mut tracer = PathTracer::default();
+    sort_part(workflows, part, &mut tracer);
+    tracer
+}
+
 fn rate_part(part: &Rating) -> Value {
-    part.x + part.m + part.a + part.s
+    part.0.iter().sum()
 }
 
 fn solve1(workflows: &WorkflowMap, parts: &Vec<Rating>) -> u32 {
     let mut score = 0;
 
     for part in parts {
-        if sort_part(workflows, part) {
+        if sort_part(workflows, part, &mut NoTracer) {
             score += rate_part(part);
         }
     }
@@ -223,57 +481,81 @@ fn solve1(workflows: &WorkflowMap, parts: &Vec<Rating>) -> u32 {
 
 // --- Part2 ---
 
-#[derive (Debug, Clone, PartialEq, Eq)]
-struct PartSet {
-    x: Interval<Value>,
-    m: Interval<Value>,
-    a: Interval<Value>,
-    s: Interval<Value>
-}
+type PartSet = DynRect<Value>;
 
-impl PartSet {
-    fn cardinal(&self) -> u64 {
-        self.x.cardinal() as u64 *
-        self.m.cardinal() as u64 *
-        self.a.cardinal() as u64 *
-        self.s.cardinal() as u64 
+impl Variable {
+    fn axis(self) -> usize {
+        self.0
     }
 }
 
-impl Index<Variable> for PartSet {
-    type Output = Interval<Value>;
+// The same range of values for every variable
+pub fn uniform_bounds(variables: &Variables, range: Interval<Value>) -> PartSet {
+    DynRect(vec![range; variables.len()])
+}
 
-    fn index(&self, index: Variable) -> &Interval<Value> {
-        match index {
-            Variable::X => &self.x,
-            Variable::M => &self.m,
-            Variable::A => &self.a,
-            Variable::S => &self.s
-        }
-    }
+fn swap<T>((a, b): (T, T)) -> (T, T) {
+    (b, a)
 }
 
-impl IndexMut<Variable> for PartSet {
-    fn index_mut(&mut self, index: Variable) -> &mut Interval<Value> {
-        match index {
-            Variable::X => &mut self.x,
-            Variable::M => &mut self.m,
-            Variable::A => &mut self.a,
-            Variable::S => &mut self.s
+// Splits the parts into disjoint boxes satisfying the condition and disjoint
+// boxes that don't
+fn split(parts: &PartSet, condition: &Condition) -> (Vec<PartSet>, Vec<PartSet>) {
+    let (accepted, rejected) = match condition {
+        Condition::Lower(var, val) => parts.split_before(var.axis(), *val),
+        Condition::LowerOrEqual(var, val) => parts.split_after(var.axis(), *val),
+        Condition::Greater(var, val) => swap(parts.split_after(var.axis(), *val)),
+        Condition::GreaterOrEqual(var, val) => swap(parts.split_before(var.axis(), *val)),
+        Condition::Equal(var, val) | Condition::Different(var, val) => {
+            let (below, rest) = parts.split_before(var.axis(), *val);
+            let (equal, above) = match rest {
+                Some(rest) => rest.split_after(var.axis(), *val),
+                None => (None, None)
+            };
+            let others = below.into_iter().chain(above).collect();
+            let equal = equal.into_iter().collect();
+            return match condition {
+                Condition::Equal(..) => (equal, others),
+                _ => (others, equal)
+            }
+        },
+        Condition::And(conditions) => {
+            let mut accepted = vec![parts.clone()];
+            let mut rejected = Vec::new();
+            for condition in conditions {
+                let mut remaining = Vec::new();
+                for parts in &accepted {
+                    let (a, r) = split(parts, condition);
+                    remaining.extend(a);
+                    rejected.extend(r);
+                }
+                accepted = remaining;
+            }
+            return (accepted, rejected)
         }
-    }
+    };
+    (accepted.into_iter().collect(), rejected.into_iter().collect())
+}
+
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub parts: PartSet,
+    pub path: Vec<Step>
 }
 
 fn do_action(
         workflows: &WorkflowMap,
         parts: PartSet,
-        action: &Action) -> u64 {
+        action: &Action,
+        path: &mut Vec<Step>,
+        regions: &mut Vec<Region>,
+        tracer: &mut impl Tracer) {
     match action {
-        Action::Accept => parts.cardinal(),
-        Action::Reject => 0,
+        Action::Accept => regions.push(Region { parts, path: path.clone() }),
+        Action::Reject => (),
         Action::Follow(target) => {
             let workflow = workflows.get(target).unwrap();
-            do_rule(workflows, parts, workflow, 0)
+            do_rule(workflows, parts, workflow, 0, path, regions, tracer)
         }
     }
 }
@@ -282,54 +564,319 @@ fn do_rule(
         workflows: &WorkflowMap,
         parts: PartSet,
         workflow: &Workflow,
-        rule_number: usize) -> u64 {
-    println!("{}:{rule_number} / {parts:?}", workflow.name);
+        rule_number: usize,
+        path: &mut Vec<Step>,
+        regions: &mut Vec<Region>,
+        tracer: &mut impl Tracer) {
+    tracer.split(workflow, rule_number, &parts);
 
     if rule_number >= workflow.rules.len() {
-        do_action(workflows, parts, &workflow.default)
+        path.push(Step { workflow: workflow.name.clone(), rule: None });
+        do_action(workflows, parts, &workflow.default, path, regions, tracer);
+        path.pop();
     }
     else {
         let rule = &workflow.rules[rule_number];
-        let (var, intervals, accepted) = match rule.condition {
-            Condition::Lower(var, val) =>
-                (var, parts[var].split_before(val), SplitResult::Below),
-            Condition::Greater(var, val) =>
-                (var, parts[var].split_after(val), SplitResult::Above),
+        let (accepted, rejected) = split(&parts, &rule.condition);
+
+        path.push(Step { workflow: workflow.name.clone(), rule: Some (rule_number) });
+        for accepted in accepted {
+            do_action(workflows, accepted, &rule.action, path, regions, tracer);
+        }
+        path.pop();
+        for rejected in rejected {
+            do_rule(workflows, rejected, workflow, rule_number + 1, path, regions, tracer)
+        }
+    }
+}
+
+// Disjoint regions of accepted parts, with the path leading to each of them
+pub fn accepted_regions(workflows: &WorkflowMap, bounds: &PartSet, tracer: &mut impl Tracer)
+        -> Vec<Region> {
+    let mut regions = Vec::new();
+    let start = Action::Follow(String::from("in"));
+    do_action(workflows, bounds.clone(), &start, &mut Vec::new(), &mut regions, tracer);
+    regions
+}
+
+pub fn regions_to_csv(variables: &Variables, regions: &[Region]) -> String {
+    let mut csv = String::new();
+    for name in &variables.0 {
+        csv += &format!("{name}_min,{name}_max,");
+    }
+    csv += "count,path\n";
+    for region in regions {
+        for Interval(min, max) in &region.parts.0 {
+            csv += &format!("{min},{max},");
+        }
+        let path: Vec<String> = region.path.iter().map(Step::to_string).collect();
+        csv += &format!("{},{}\n", region.parts.volume(), path.join(" -> "));
+    }
+    csv
+}
+
+pub fn solve2(workflows: &WorkflowMap, bounds: &PartSet) -> u128 {
+    accepted_regions(workflows, bounds, &mut NoTracer).iter()
+        .map(|region| region.parts.volume())
+        .sum()
+}
+
+
+// --- Compilation ---
+
+#[derive (Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Leaf(bool),
+    Test(Condition, usize, usize)
+}
+
+// Workflows flattened into a single binary decision tree, where every test
+// only happens if both outcomes are possible
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub struct DecisionTree {
+    nodes: Vec<Node>,
+    root: usize,
+    // Tests whose outcome is fixed within the bounds are pruned, so the tree
+    // can only classify parts inside them
+    bounds: PartSet
+}
+
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub enum CompileError {
+    Undefined(WorkflowName),
+    Cycle(WorkflowName)
+}
+
+const ACCEPT: usize = 0;
+const REJECT: usize = 1;
+
+struct Compiler<'a> {
+    workflows: &'a WorkflowMap<'a>,
+    nodes: Vec<Node>,
+    stack: Vec<&'a WorkflowName>
+}
+
+impl<'a> Compiler<'a> {
+    fn compile_action(&mut self, parts: PartSet, action: &'a Action)
+            -> Result<usize, CompileError> {
+        match action {
+            Action::Accept => Ok (ACCEPT),
+            Action::Reject => Ok (REJECT),
+            Action::Follow(target) => {
+                if self.stack.contains(&target) {
+                    return Err (CompileError::Cycle(target.clone()))
+                }
+                let workflow = *self.workflows.get(target)
+                    .ok_or_else(|| CompileError::Undefined(target.clone()))?;
+                self.stack.push(target);
+                let node = self.compile_rule(parts, workflow, 0);
+                self.stack.pop();
+                node
+            }
+        }
+    }
+
+    fn compile_rule(&mut self, parts: PartSet, workflow: &'a Workflow, rule_number: usize)
+            -> Result<usize, CompileError> {
+        let Some(rule) = workflow.rules.get(rule_number) else {
+            return self.compile_action(parts, &workflow.default)
         };
 
-        let mut count = 0;
+        // Pruning only needs an over-approximation of each side
+        let (accepted, rejected) = split(&parts, &rule.condition);
+        let hull = |boxes: Vec<PartSet>| boxes.into_iter().reduce(|a, b| a.hull(&b));
 
-        for (split_result, interval) in intervals {
-            let mut new_state = parts.clone();
-            new_state[var] = interval;
-            if split_result == accepted {
-                count += do_action(workflows, new_state, &rule.action)
+        match (hull(accepted), hull(rejected)) {
+            (Some(accepted), None) => self.compile_action(accepted, &rule.action),
+            (None, Some(rejected)) => self.compile_rule(rejected, workflow, rule_number + 1),
+            (None, None) => Ok (REJECT),
+            (Some(accepted), Some(rejected)) => {
+                let then = self.compile_action(accepted, &rule.action)?;
+                let otherwise = self.compile_rule(rejected, workflow, rule_number + 1)?;
+                if then == otherwise {
+                    Ok (then)
+                }
+                else {
+                    self.nodes.push(Node::Test(rule.condition.clone(), then, otherwise));
+                    Ok (self.nodes.len() - 1)
+                }
             }
-            else {
-                count += do_rule(workflows, new_state, workflow, rule_number + 1)
+        }
+    }
+}
+
+impl DecisionTree {
+    pub fn compile(workflows: &WorkflowMap, bounds: &PartSet) -> Result<Self, CompileError> {
+        let start = Action::Follow(String::from("in"));
+        let mut compiler = Compiler {
+            workflows,
+            nodes: vec![Node::Leaf(true), Node::Leaf(false)],
+            stack: Vec::new()
+        };
+        let root = compiler.compile_action(bounds.clone(), &start)?;
+        Ok (DecisionTree { nodes: compiler.nodes, root, bounds: bounds.clone() })
+    }
+
+    pub fn size(&self) -> usize {
+        self.nodes.len()
+    }
+
+    // None for parts outside the bounds the tree was compiled for
+    pub fn classify(&self, part: &Rating) -> Option<bool> {
+        if part.0.len() != self.bounds.0.len() || !self.bounds.contains(&part.0) {
+            return None
+        }
+        let mut node = self.root;
+        loop {
+            match &self.nodes[node] {
+                Node::Leaf(accepted) => return Some (*accepted),
+                Node::Test(condition, then, otherwise) =>
+                    node = if check_condition(condition, part) { *then } else { *otherwise }
             }
         }
+    }
+}
+
 
-        count
+// --- Analysis ---
+
+#[derive (Debug, Clone, Default, PartialEq, Eq)]
+pub struct Analysis {
+    // (workflow, target) for each target that is not defined
+    pub undefined: Vec<(WorkflowName, WorkflowName)>,
+    pub unreachable: Vec<WorkflowName>,
+    pub cycles: Vec<Vec<WorkflowName>>,
+    // (workflow, rule number) of rules no part can ever match
+    pub dead_rules: Vec<(WorkflowName, usize)>,
+    // (workflow, rule number) of rules that can be removed without changing
+    // the outcome, as they lead to the fall-through action anyway
+    pub redundant_rules: Vec<(WorkflowName, usize)>
+}
+
+impl Analysis {
+    pub fn is_clean(&self) -> bool {
+        *self == Analysis::default()
     }
 }
 
-fn solve2(workflows: &WorkflowMap) -> u64 {
-    let every_parts = PartSet {
-            x: Interval(1, 4000),
-            m: Interval(1, 4000),
-            a: Interval(1, 4000),
-            s: Interval(1, 4000)
-        };
-    do_action(workflows, every_parts, &Action::Follow(String::from("in")))
+fn targets(workflow: &Workflow) -> impl Iterator<Item = &WorkflowName> {
+    workflow.rules.iter()
+        .map(|rule| &rule.action)
+        .chain([&workflow.default])
+        .filter_map(|action| match action {
+            Action::Follow(target) => Some (target),
+            _ => None
+        })
+}
+
+fn find_cycles(workflows: &WorkflowMap) -> Vec<Vec<WorkflowName>> {
+    #[derive (Clone, Copy, PartialEq, Eq)]
+    enum State { Visiting, Done }
+
+    fn visit<'a>(
+            workflows: &WorkflowMap<'a>,
+            name: &'a WorkflowName,
+            states: &mut HashMap<&'a WorkflowName, State>,
+            stack: &mut Vec<&'a WorkflowName>,
+            cycles: &mut Vec<Vec<WorkflowName>>) {
+        states.insert(name, State::Visiting);
+        stack.push(name);
+        if let Some(workflow) = workflows.get(name) {
+            for target in targets(workflow) {
+                match states.get(target) {
+                    None => visit(workflows, target, states, stack, cycles),
+                    Some(State::Visiting) => {
+                        let start = stack.iter().position(|&n| n == target).unwrap();
+                        cycles.push(stack[start..].iter().map(|&n| n.clone()).collect());
+                    },
+                    Some(State::Done) => ()
+                }
+            }
+        }
+        stack.pop();
+        states.insert(name, State::Done);
+    }
+
+    let mut names: Vec<&WorkflowName> = workflows.keys().copied().collect();
+    names.sort();
+    let mut states = HashMap::new();
+    let mut cycles = Vec::new();
+    for name in names {
+        if !states.contains_key(name) {
+            visit(workflows, name, &mut states, &mut Vec::new(), &mut cycles);
+        }
+    }
+    cycles
 }
 
-pub fn solve(input: &str) -> (u32,u64) {
-    let (_,(workflows, parts)) = parser::parse(input).unwrap();
+pub fn analyse(workflows: &[Workflow], bounds: &PartSet) -> Analysis {
+    let map: WorkflowMap = workflows.iter().map(|w| (&w.name, w)).collect();
+    let mut analysis = Analysis::default();
+
+    for workflow in workflows {
+        for target in targets(workflow) {
+            if !map.contains_key(target) {
+                analysis.undefined.push((workflow.name.clone(), target.clone()));
+            }
+        }
+    }
+
+    let start = String::from("in");
+    let mut reachable = HashSet::from([&start]);
+    let mut queue = vec![&start];
+    while let Some(name) = queue.pop() {
+        if let Some(workflow) = map.get(name) {
+            for target in targets(workflow) {
+                if reachable.insert(target) {
+                    queue.push(target);
+                }
+            }
+        }
+    }
+    analysis.unreachable = workflows.iter()
+        .map(|w| &w.name)
+        .filter(|name| !reachable.contains(name))
+        .cloned()
+        .collect();
+
+    analysis.cycles = find_cycles(&map);
+
+    for workflow in workflows {
+        let mut remaining = vec![bounds.clone()];
+        for (i, rule) in workflow.rules.iter().enumerate() {
+            let mut accepted = false;
+            let mut rejected = Vec::new();
+            for parts in &remaining {
+                let (a, r) = split(parts, &rule.condition);
+                accepted |= !a.is_empty();
+                rejected.extend(r);
+            }
+            if !accepted {
+                analysis.dead_rules.push((workflow.name.clone(), i));
+            }
+            remaining = rejected;
+        }
+
+        let redundant = workflow.rules.iter()
+            .rev()
+            .take_while(|rule| rule.action == workflow.default)
+            .count();
+        for i in workflow.rules.len() - redundant..workflow.rules.len() {
+            analysis.redundant_rules.push((workflow.name.clone(), i));
+        }
+    }
+
+    analysis
+}
+
+// Part 2 is counted on u128, as inputs with more variables overflow u64
+pub fn solve(input: &str) -> (u32,u128) {
+    let (_,(variables, workflows, parts)) = parser::parse(input).unwrap();
     let workflows: WorkflowMap =
         workflows.iter().map(|w| (&w.name, w)).collect();
+    let bounds = uniform_bounds(&variables, Interval(1, 4000));
 
-    (solve1(&workflows, &parts), solve2(&workflows))
+    (solve1(&workflows, &parts), solve2(&workflows, &bounds))
 }
 
 #[test]
@@ -343,3 +890,346 @@ fn day19_input() {
     let solution = solve(include_str!("../inputs/day19-input"));
     assert_eq!(solution, (418498, 123331556462603));
 }
+
+#[test]
+fn day19_analysis() {
+    let (_,(variables, workflows, _)) =
+        parser::parse(include_str!("../inputs/day19-example")).unwrap();
+    let analysis = analyse(&workflows, &uniform_bounds(&variables, Interval(1, 4000)));
+    assert_eq!(analysis.redundant_rules,
+        vec![(String::from("lnx"), 0), (String::from("gd"), 0)]);
+    assert!(analysis.undefined.is_empty());
+    assert!(analysis.unreachable.is_empty());
+    assert!(analysis.cycles.is_empty());
+    assert!(analysis.dead_rules.is_empty());
+
+    let input = "in{x<10:a,x<5:R,b}\na{m>5:b,zz}\nb{a<3:a,R}\nc{x>1:R,A}\n\n{x=1,m=1,a=1,s=1}";
+    let (_,(variables, workflows, _)) = parser::parse(input).unwrap();
+    let analysis = analyse(&workflows, &uniform_bounds(&variables, Interval(1, 4000)));
+    assert_eq!(analysis.undefined, vec![(String::from("a"), String::from("zz"))]);
+    assert_eq!(analysis.unreachable, vec![String::from("c")]);
+    assert_eq!(analysis.cycles, vec![vec![String::from("a"), String::from("b")]]);
+    assert_eq!(analysis.dead_rules, vec![(String::from("in"), 1)]);
+    assert!(analysis.redundant_rules.is_empty());
+    assert!(!analysis.is_clean());
+}
+
+#[cfg(test)]
+fn random_ratings(rng: &mut impl rand::Rng, variables: &Variables, count: usize) -> Vec<Rating> {
+    (0..count)
+        .map(|_| Valuation((0..variables.len()).map(|_| rng.gen_range(1..=4000)).collect()))
+        .collect()
+}
+
+// Random workflows, where workflows only follow later ones so that there is
+// no cycle, and random ratings over the variables they use
+#[cfg(test)]
+fn random_system(rng: &mut impl rand::Rng, max_value: Value) -> Input {
+    fn comparison(rng: &mut impl rand::Rng, variables: &mut Variables, max_value: Value)
+            -> Condition {
+        let names = ["x", "m", "a", "s", "foo"];
+        let v = variables.intern(names[rng.gen_range(0..names.len())]);
+        let val = rng.gen_range(0..=max_value);
+        match rng.gen_range(0..6) {
+            0 => Condition::Lower(v, val),
+            1 => Condition::LowerOrEqual(v, val),
+            2 => Condition::Greater(v, val),
+            3 => Condition::GreaterOrEqual(v, val),
+            4 => Condition::Equal(v, val),
+            _ => Condition::Different(v, val)
+        }
+    }
+
+    fn action(rng: &mut impl rand::Rng, names: &[String], current: usize) -> Action {
+        match rng.gen_range(0..4) {
+            0 => Action::Accept,
+            1 => Action::Reject,
+            _ if current + 1 < names.len() =>
+                Action::Follow(names[rng.gen_range(current + 1..names.len())].clone()),
+            _ => Action::Accept
+        }
+    }
+
+    let names: Vec<String> = (0..rng.gen_range(1..=6))
+        .map(|i| if i == 0 { String::from("in") } else { format!("w{}", (b'a' + i) as char) })
+        .collect();
+    let mut variables = Variables::default();
+    let mut workflows = Vec::new();
+    for (i, name) in names.iter().enumerate() {
+        let mut rules = Vec::new();
+        for _ in 0..rng.gen_range(1..=4) {
+            let mut conditions: Vec<Condition> = (0..rng.gen_range(1..=3))
+                .map(|_| comparison(rng, &mut variables, max_value))
+                .collect();
+            let condition =
+                if conditions.len() == 1 { conditions.pop().unwrap() }
+                else { Condition::And(conditions) };
+            rules.push(Rule { condition, action: action(rng, &names, i) });
+        }
+        let default = action(rng, &names, i);
+        workflows.push(Workflow { name: name.clone(), rules, default });
+    }
+
+    let ratings = (0..rng.gen_range(1..=5))
+        .map(|_| Valuation((0..variables.len()).map(|_| rng.gen_range(0..=max_value)).collect()))
+        .collect();
+    (variables, workflows, ratings)
+}
+
+#[test]
+fn day19_compiled() {
+    use rand::{SeedableRng, rngs::StdRng};
+
+    let (_,(variables, workflows, parts)) =
+        parser::parse(include_str!("../inputs/day19-input")).unwrap();
+    let workflows: WorkflowMap = workflows.iter().map(|w| (&w.name, w)).collect();
+    let bounds = uniform_bounds(&variables, Interval(1, 4000));
+    let tree = DecisionTree::compile(&workflows, &bounds).unwrap();
+
+    let mut rng = StdRng::seed_from_u64(19);
+    for part in parts.iter().chain(&random_ratings(&mut rng, &variables, 10000)) {
+        assert_eq!(tree.classify(part), Some (sort_part(&workflows, part, &mut NoTracer)),
+            "{part:?}");
+    }
+
+    // The test on x is pruned, as it always fails within the bounds
+    let (_,(variables, workflows, _)) = parser::parse("in{x>4000:R,A}\n\n{x=1}").unwrap();
+    let workflows: WorkflowMap = workflows.iter().map(|w| (&w.name, w)).collect();
+    let bounds = uniform_bounds(&variables, Interval(1, 4000));
+    let tree = DecisionTree::compile(&workflows, &bounds).unwrap();
+    assert_eq!(tree.size(), 2);
+    assert_eq!(tree.classify(&Valuation(vec![4000])), Some (true));
+    assert_eq!(tree.classify(&Valuation(vec![5000])), None);
+    assert_eq!(tree.classify(&Valuation(vec![1, 1])), None);
+
+    let input = "in{x<10:a,R}\na{m>5:b,A}\nb{a<3:a,c}\n\n{x=1,m=1,a=1,s=1}";
+    let (_,(variables, workflows, _)) = parser::parse(input).unwrap();
+    let workflows: WorkflowMap = workflows.iter().map(|w| (&w.name, w)).collect();
+    let bounds = uniform_bounds(&variables, Interval(1, 4000));
+    assert_eq!(DecisionTree::compile(&workflows, &bounds), Err(CompileError::Cycle(String::from("a"))));
+}
+
+#[test]
+#[ignore]
+fn day19_benchmark() {
+    use std::time::Instant;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    let (_,(variables, workflows, _)) =
+        parser::parse(include_str!("../inputs/day19-input")).unwrap();
+    let workflows: WorkflowMap = workflows.iter().map(|w| (&w.name, w)).collect();
+    let tree = DecisionTree::compile(&workflows, &uniform_bounds(&variables, Interval(1, 4000)))
+        .unwrap();
+    let parts = random_ratings(&mut StdRng::seed_from_u64(19), &variables, 1_000_000);
+
+    let start = Instant::now();
+    let interpreted = parts.iter()
+        .filter(|part| sort_part(&workflows, part, &mut NoTracer))
+        .count();
+    let interpreter_time = start.elapsed();
+
+    let start = Instant::now();
+    let compiled = parts.iter().filter(|part| tree.classify(part) == Some (true)).count();
+    let compiled_time = start.elapsed();
+
+    assert_eq!(interpreted, compiled);
+    println!("interpreter: {interpreter_time:?}, compiled ({} nodes): {compiled_time:?}",
+        tree.size());
+}
+
+#[test]
+fn day19_regions() {
+    let (_,(variables, workflows, parts)) =
+        parser::parse(include_str!("../inputs/day19-example")).unwrap();
+    let workflows: WorkflowMap = workflows.iter().map(|w| (&w.name, w)).collect();
+    let bounds = uniform_bounds(&variables, Interval(1, 4000));
+    let regions = accepted_regions(&workflows, &bounds, &mut NoTracer);
+
+    let set: BoxSet<PartSet> = regions.iter().map(|r| r.parts.clone()).collect();
+    assert_eq!(set.volume(), 167409079868000);
+    assert_eq!(set.boxes().len(), regions.len());
+
+    for part in &parts {
+        let region = regions.iter().find(|r| r.parts.contains(&part.0));
+        assert_eq!(region.is_some(), sort_part(&workflows, part, &mut NoTracer));
+    }
+
+    // {x=787,m=2655,a=1222,s=2876}
+    let region = regions.iter().find(|r| r.parts.contains(&parts[0].0)).unwrap();
+    let path: Vec<String> = region.path.iter().map(Step::to_string).collect();
+    assert_eq!(path, vec!["in", "qqz:0", "qs", "lnx:0"]);
+
+    let csv = regions_to_csv(&variables, &regions);
+    assert_eq!(csv.lines().count(), regions.len() + 1);
+    assert!(csv.contains(",in -> qqz:0 -> qs -> lnx:0\n"));
+}
+
+#[test]
+fn day19_trace() {
+    let (_,(_, workflows, parts)) = parser::parse(include_str!("../inputs/day19-example")).unwrap();
+    let workflows: WorkflowMap = workflows.iter().map(|w| (&w.name, w)).collect();
+
+    let trace = trace_part(&workflows, &parts[0]);
+    assert_eq!(trace.to_string(), "in -> qqz:0 -> qs -> lnx:0 -> A");
+    let trace = trace_part(&workflows, &parts[1]);
+    assert_eq!(trace.to_string(), "in:0 -> px -> rfg:0 -> gd -> R");
+}
+
+#[test]
+fn day19_custom_variables() {
+    let input = "in{temp>20:hot,humidity<30:A,R}\nhot{wind>2:A,humidity>60:R,A}\n\n\
+        {temp=25,wind=1,humidity=70}\n{temp=10,humidity=5}\n{temp=30,wind=5,humidity=90}";
+    let (_,(variables, workflows, parts)) = parser::parse(input).unwrap();
+    assert_eq!(variables.len(), 3);
+    assert_eq!(variables.name(variables.get("wind").unwrap()), "wind");
+    assert!(variables.get("hot").is_none());
+    let workflows: WorkflowMap = workflows.iter().map(|w| (&w.name, w)).collect();
+
+    let accepted: Vec<bool> = parts.iter()
+        .map(|part| sort_part(&workflows, part, &mut NoTracer))
+        .collect();
+    assert_eq!(accepted, vec![false, true, true]);
+    assert_eq!(solve1(&workflows, &parts), 15 + 125);
+
+    // Brute force over small bounds, with a different range per variable
+    let (t, h, w) = (variables.get("temp").unwrap(), variables.get("humidity").unwrap(),
+        variables.get("wind").unwrap());
+    let mut bounds = uniform_bounds(&variables, Interval(0, 0));
+    bounds[t.axis()] = Interval(0, 40);
+    bounds[w.axis()] = Interval(0, 5);
+    bounds[h.axis()] = Interval(0, 100);
+    let mut count = 0;
+    for temp in 0..=40 {
+        for wind in 0..=5 {
+            for humidity in 0..=100 {
+                let mut part = Valuation(vec![0; 3]);
+                part[t] = temp;
+                part[w] = wind;
+                part[h] = humidity;
+                if sort_part(&workflows, &part, &mut NoTracer) {
+                    count += 1;
+                }
+            }
+        }
+    }
+    assert_eq!(solve2(&workflows, &bounds), count);
+
+    let csv = regions_to_csv(&variables, &accepted_regions(&workflows, &bounds, &mut NoTracer));
+    assert!(csv.starts_with("temp_min,temp_max,wind_min,wind_max,humidity_min,humidity_max,count"));
+
+    // Six variables overflow u64
+    let (_, count) = solve("in{a>4000:R,A}\n\n{a=1,b=1,c=1,d=1,e=1,f=1}");
+    assert_eq!(count, 4000u128.pow(6));
+}
+
+#[test]
+fn day19_rich_conditions() {
+    let input = "in{x>=3&y<=10:a,z==7:A,x!=12:b,R}\n\
+        a{y!=4&z<12&x<=9:A,x==3:R,b}\n\
+        b{z>=5&z<=9:R,y>12&x>=8:A,y==0:A,R}\n\n\
+        {x=3,y=4,z=7}\n{x=13,y=11,z=7}\n{x=12,y=0,z=1}";
+    let (_,(variables, workflows, parts)) = parser::parse(input).unwrap();
+    assert_eq!(workflows[0].rules[0].condition, Condition::And(vec![
+        Condition::GreaterOrEqual(variables.get("x").unwrap(), 3),
+        Condition::LowerOrEqual(variables.get("y").unwrap(), 10)]));
+    let analysis = analyse(&workflows, &uniform_bounds(&variables, Interval(0, 15)));
+    assert!(analysis.dead_rules.is_empty());
+    let workflows: WorkflowMap = workflows.iter().map(|w| (&w.name, w)).collect();
+
+    let accepted: Vec<bool> = parts.iter()
+        .map(|part| sort_part(&workflows, part, &mut NoTracer))
+        .collect();
+    assert_eq!(accepted, vec![false, true, true]);
+
+    let bounds = uniform_bounds(&variables, Interval(0, 15));
+    let tree = DecisionTree::compile(&workflows, &bounds).unwrap();
+    let mut count = 0;
+    for x in 0..16 {
+        for y in 0..16 {
+            for z in 0..16 {
+                let part = Valuation(vec![x, y, z]);
+                let accepted = sort_part(&workflows, &part, &mut NoTracer);
+                assert_eq!(tree.classify(&part), Some (accepted));
+                if accepted {
+                    count += 1;
+                }
+            }
+        }
+    }
+    assert_eq!(solve2(&workflows, &bounds), count);
+    let regions = accepted_regions(&workflows, &bounds, &mut NoTracer);
+    let set: BoxSet<PartSet> = regions.iter().map(|r| r.parts.clone()).collect();
+    assert_eq!(set.volume(), count);
+}
+
+#[test]
+fn day19_round_trip() {
+    use rand::{SeedableRng, rngs::StdRng};
+
+    let input = include_str!("../inputs/day19-example");
+    let (_,(variables, workflows, ratings)) = parser::parse(input).unwrap();
+    assert_eq!(variables.display(&workflows[0]).to_string(), "px{a<2006:qkq,m>2090:A,rfg}");
+    assert_eq!(variables.display(&ratings[0]).to_string(), "{x=787,m=2655,a=1222,s=2876}");
+    assert_eq!(format_input(&variables, &workflows, &ratings), input);
+
+    // Fields are not kept as written, only their values
+    let (_,(variables, workflows, ratings)) =
+        parser::parse("in{x<2:A,R}\n\n{x=1,m=2}\n{m=3}").unwrap();
+    assert_eq!(format_input(&variables, &workflows, &ratings),
+        "in{x<2:A,R}\n\n{x=1,m=2}\n{x=0,m=3}\n");
+
+    // Generated ratings list every variable in order, as format_input does
+    let mut rng = StdRng::seed_from_u64(43);
+    for _ in 0..500 {
+        let system = random_system(&mut rng, 4000);
+        let (variables, workflows, ratings) = &system;
+        let text = format_input(variables, workflows, ratings);
+        let (_,parsed) = parser::parse(&text).unwrap_or_else(|e| panic!("{e} on\n{text}"));
+        assert_eq!(parsed, system, "{text}");
+    }
+}
+
+// Every rating within the bounds
+#[cfg(test)]
+fn all_ratings(bounds: &PartSet) -> Vec<Rating> {
+    let mut ratings = vec![Valuation(Vec::new())];
+    for &Interval(min, max) in &bounds.0 {
+        ratings = ratings.iter()
+            .flat_map(|rating| (min..=max).map(|x| {
+                let mut rating = rating.clone();
+                rating.0.push(x);
+                rating
+            }))
+            .collect();
+    }
+    ratings
+}
+
+#[test]
+fn day19_differential() {
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    let mut rng = StdRng::seed_from_u64(44);
+    for _ in 0..1000 {
+        let (variables, workflows, ratings) = random_system(&mut rng, 8);
+        let text = format_input(&variables, &workflows, &ratings);
+        let workflows: WorkflowMap = workflows.iter().map(|w| (&w.name, w)).collect();
+        let bounds = DynRect((0..variables.len())
+            .map(|_| {
+                let min = rng.gen_range(0..=8);
+                Interval(min, min + rng.gen_range(0..=4))
+            })
+            .collect());
+
+        let expected = all_ratings(&bounds).iter()
+            .filter(|part| sort_part(&workflows, part, &mut NoTracer))
+            .count() as u128;
+
+        let mut regions = Vec::new();
+        let start = Action::Follow(String::from("in"));
+        do_action(&workflows, bounds.clone(), &start, &mut Vec::new(), &mut regions, &mut NoTracer);
+        let count: u128 = regions.iter().map(|region| region.parts.volume()).sum();
+
+        assert_eq!(count, expected, "bounds {bounds:?} on\n{text}");
+    }
+}
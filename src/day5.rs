@@ -1,30 +1,8 @@
-use std::ops;
+use std::{fmt, collections::{HashMap, HashSet}};
+use crate::utils::intervals::*;
 
-#[derive (Debug, Clone, Copy)]
-struct Interval(i64, i64);
-
-impl ops::Add<i64> for &Interval {
-    type Output = Interval;
-
-    fn add(self, x: i64) -> Interval {
-        Interval(self.0 + x, self.1 + x)
-    }
-}
-
-impl Interval {
-    fn is_empty(&self) -> bool {
-        self.0 > self.1
-    }
-
-    fn wrap_empty(&self) -> Option<Self> {
-        if self.is_empty() {
-            None
-        }
-        else {
-            Some (*self)
-        }
-    }
-}
+type Interval = crate::utils::intervals::Interval<i64>;
+type Intervals = IntervalSet<i64>;
 
 #[derive (Debug, Clone)]
 pub struct Range {
@@ -48,6 +26,10 @@ impl Range {
         )
     }
 
+    fn offset(&self) -> i64 {
+        self.destination_start - self.source_start
+    }
+
     fn map(&self, x: i64) -> Option<i64> {
         if x >= self.source_start && x < self.source_start + self.length {
             Some (x + self.destination_start - self.source_start)
@@ -57,31 +39,18 @@ impl Range {
         }
     }
 
-    fn map_interval(&self, interval: &Interval)
-            -> (Option<Interval>, Vec<Interval>) {
-        let offset_interval =
-            interval + (self.destination_start - self.source_start);
-        let src = self.source();
-        let dst = self.destination();
-        let diff_left = Interval(
-            interval.0,
-            interval.1.min(src.0 - 1));
-        let inter = Interval(
-            offset_interval.0.max(dst.0),
-            offset_interval.1.min(dst.1));
-        let diff_right = Interval(
-            interval.0.max(src.1 + 1), 
-            interval.1);
-
-        let diffs = vec![diff_left.wrap_empty(), diff_right.wrap_empty()];
-        let unwrapped_diffs : Vec<Interval> =
-            diffs.into_iter().flatten().collect();
-        (inter.wrap_empty(), unwrapped_diffs)
+    fn unmap(&self, y: i64) -> Option<i64> {
+        if y >= self.destination_start && y < self.destination_start + self.length {
+            Some (y + self.source_start - self.destination_start)
+        }
+        else {
+            None
+        }
     }
 }
 
 #[derive (Debug, Clone)]
-struct Ranges (Vec<Range>);
+pub struct Ranges (Vec<Range>);
 
 impl Ranges {
     fn map(&self, x: i64) -> i64 {
@@ -93,43 +62,392 @@ impl Ranges {
         x
     }
 
+    // Every x such that map(x) == y, taking into account that a range is
+    // shadowed by the previous ones and that unmapped values are left as is
+    fn unmap(&self, y: i64) -> Vec<i64> {
+        let mut result: Vec<i64> = self.0.iter()
+            .filter_map(|range| range.unmap(y))
+            .chain([y])
+            .filter(|&x| self.map(x) == y)
+            .collect();
+        result.sort();
+        result.dedup();
+        result
+    }
+
+    fn unmap_intervals(&self, intervals: &Intervals) -> Intervals {
+        let mut result = Intervals::default();
+        let mut shadowed = Intervals::default();
+
+        for range in &self.0 {
+            let destination = Intervals::from(range.destination());
+            let sources = intervals.intersection(&destination).shift(-range.offset());
+            result = result.union(&sources.difference(&shadowed));
+            shadowed = shadowed.union(&Intervals::from(range.source()));
+        }
+
+        result.union(&intervals.difference(&shadowed))
+    }
+
     fn map_vector(&self, ids: Vec<i64>) -> Vec<i64> {
         ids.iter().map(|id| self.map(*id)).collect()
     }
 
-    fn map_intervals(&self, intervals: &[Interval]) -> Vec<Interval> {
-        let mut result : Vec<Interval> = Vec::new();
-        let mut rest : Vec<Interval> = Vec::from(intervals);
+    fn map_intervals(&self, intervals: &Intervals) -> Intervals {
+        let mut result = Intervals::default();
+        let mut rest = intervals.clone();
 
         for range in &self.0 {
-            let mut next_rest : Vec<Interval> = Vec::new();
+            let source = Intervals::from(range.source());
+            result = result.union(&rest.intersection(&source).shift(range.offset()));
+            rest = rest.difference(&source);
+        }
+
+        result.union(&rest)
+    }
+}
+
+#[derive (Debug, Clone, Default, PartialEq, Eq)]
+pub struct RangesReport {
+    // Pairs of range indices whose sources overlap, with the overlap
+    pub source_overlaps: Vec<(usize, usize, Interval)>,
+    // Pairs of range indices whose destinations overlap, making the map
+    // non-injective
+    pub destination_overlaps: Vec<(usize, usize, Interval)>,
+    // Range indices whose destination overlaps values outside every source,
+    // which are mapped to themselves, also making the map non-injective
+    pub identity_overlaps: Vec<(usize, Interval)>,
+    // Values between the ranges that are left unmapped
+    pub gaps: Vec<Interval>
+}
+
+impl RangesReport {
+    pub fn is_valid(&self) -> bool {
+        self.source_overlaps.is_empty() && self.destination_overlaps.is_empty() &&
+            self.identity_overlaps.is_empty()
+    }
+}
+
+impl fmt::Display for RangesReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, j, Interval(a, b)) in &self.source_overlaps {
+            writeln!(f, "sources of ranges {i} and {j} overlap on [{a}, {b}]")?;
+        }
+        for (i, j, Interval(a, b)) in &self.destination_overlaps {
+            writeln!(f, "destinations of ranges {i} and {j} overlap on [{a}, {b}]")?;
+        }
+        for (i, Interval(a, b)) in &self.identity_overlaps {
+            writeln!(f, "destination of range {i} overlaps unmapped values on [{a}, {b}]")?;
+        }
+        for Interval(a, b) in &self.gaps {
+            writeln!(f, "identity gap [{a}, {b}]")?;
+        }
+        Ok (())
+    }
+}
+
+fn overlaps<F>(ranges: &[Range], interval: F) -> Vec<(usize, usize, Interval)>
+        where F: Fn(&Range) -> Interval {
+    let mut result = Vec::new();
+    for (i, r1) in ranges.iter().enumerate() {
+        for (j, r2) in ranges.iter().enumerate().skip(i + 1) {
+            let (a, b) = (interval(r1), interval(r2));
+            if let Some(inter) = a.intersection(&b) {
+                result.push((i, j, inter));
+            }
+        }
+    }
+    result
+}
+
+impl Ranges {
+    pub fn validate(&self) -> RangesReport {
+        let sources: Intervals = self.0.iter().map(Range::source).collect();
+        let gaps = match (sources.min(), sources.max()) {
+            (Some(min), Some(max)) =>
+                sources.complement(Interval(min, max)).intervals().to_vec(),
+            _ => Vec::new()
+        };
+
+        let unmapped = sources.complement(Interval(i64::MIN, i64::MAX));
+        let mut identity_overlaps = Vec::new();
+        for (i, range) in self.0.iter().enumerate() {
+            let destination = Intervals::from(range.destination());
+            for &inter in destination.intersection(&unmapped).intervals() {
+                identity_overlaps.push((i, inter));
+            }
+        }
+
+        RangesReport {
+            source_overlaps: overlaps(&self.0, Range::source),
+            destination_overlaps: overlaps(&self.0, Range::destination),
+            identity_overlaps,
+            gaps
+        }
+    }
+}
 
-            for itv in rest {
-                let (inter, diffs) = range.map_interval(&itv);
-                if let Some(itv) = inter {
-                    result.push(itv);
+// Piecewise function over i64 where every piece is a translation
+// x -> x + offset, which is all the almanac maps need. Pieces are sorted by
+// start and the first one always starts at i64::MIN.
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub struct PiecewiseAffine {
+    pieces: Vec<(i64, i64)>
+}
+
+impl PiecewiseAffine {
+    pub fn identity() -> Self {
+        PiecewiseAffine { pieces: vec![(i64::MIN, 0)] }
+    }
+
+    // Builds the function from a set of breakpoints, computing the offset of
+    // each piece by evaluating offset_at on its start
+    fn from_breakpoints<F>(mut breakpoints: Vec<i64>, offset_at: F) -> Self
+            where F: Fn(i64) -> i64 {
+        breakpoints.push(i64::MIN);
+        breakpoints.sort();
+        breakpoints.dedup();
+
+        let mut pieces: Vec<(i64, i64)> = Vec::new();
+        for start in breakpoints {
+            let offset = offset_at(start);
+            if pieces.last().map(|&(_, o)| o) != Some(offset) {
+                pieces.push((start, offset));
+            }
+        }
+        PiecewiseAffine { pieces }
+    }
+
+    fn from_ranges(ranges: &Ranges) -> Self {
+        let mut breakpoints = Vec::new();
+        for range in &ranges.0 {
+            let src = range.source();
+            breakpoints.push(src.0);
+            breakpoints.extend(src.1.checked_add(1));
+        }
+        Self::from_breakpoints(breakpoints, |x| {
+            ranges.0.iter()
+                .find(|range| range.map(x).is_some())
+                .map_or(0, Range::offset)
+        })
+    }
+
+    fn offset(&self, x: i64) -> i64 {
+        let i = self.pieces.partition_point(|&(start, _)| start <= x);
+        self.pieces[i - 1].1
+    }
+
+    // Bounds and offset of each piece
+    fn piece_intervals(&self) -> impl Iterator<Item = (Interval, i64)> + '_ {
+        self.pieces.iter().enumerate().map(|(i, &(start, offset))| {
+            let end = self.pieces.get(i + 1).map_or(i64::MAX, |&(s, _)| s - 1);
+            (Interval(start, end), offset)
+        })
+    }
+
+    pub fn apply(&self, x: i64) -> i64 {
+        x + self.offset(x)
+    }
+
+    // x -> next(self(x))
+    pub fn then(&self, next: &Self) -> Self {
+        let mut breakpoints: Vec<i64> = self.pieces.iter().map(|&(s, _)| s).collect();
+        for (interval, offset) in self.piece_intervals() {
+            for &(start, _) in &next.pieces {
+                let x = start.saturating_sub(offset);
+                if x > interval.0 && x <= interval.1 {
+                    breakpoints.push(x);
                 }
-                next_rest.extend(diffs);
             }
+        }
+        Self::from_breakpoints(breakpoints, |x| {
+            let offset = self.offset(x);
+            offset + next.offset(x.saturating_add(offset))
+        })
+    }
 
-            rest = next_rest;
+    // None if the function is not a bijection
+    pub fn inverse(&self) -> Option<Self> {
+        let mut images: Vec<(Interval, i64)> = self.piece_intervals()
+            .map(|(interval, offset)| {
+                let start = if interval.0 == i64::MIN { i64::MIN } else { interval.0 + offset };
+                let end = if interval.1 == i64::MAX { i64::MAX } else { interval.1 + offset };
+                (Interval(start, end), offset)
+            })
+            .collect();
+        images.sort_by_key(|(interval, _)| interval.0);
+
+        let tiled = images.first()?.0.0 == i64::MIN
+            && images.last()?.0.1 == i64::MAX
+            && images.windows(2).all(|w| w[0].0.1.checked_add(1) == Some(w[1].0.0));
+
+        if tiled {
+            let pieces = images.into_iter()
+                .map(|(interval, offset)| (interval.0, -offset))
+                .collect();
+            Some (PiecewiseAffine { pieces })
         }
+        else {
+            None
+        }
+    }
 
-        result.extend(rest);
-        result
+    pub fn image(&self, intervals: &Intervals) -> Intervals {
+        self.piece_intervals()
+            .map(|(piece, offset)|
+                intervals.intersection(&Intervals::from(piece)).shift(offset))
+            .fold(Intervals::default(), |acc, itv| acc.union(&itv))
+    }
+
+    pub fn preimage(&self, intervals: &Intervals) -> Intervals {
+        self.piece_intervals()
+            .flat_map(|(piece, offset)| {
+                intervals.intervals().iter().map(move |itv| Interval(
+                    itv.0.saturating_sub(offset).max(piece.0),
+                    itv.1.saturating_sub(offset).min(piece.1)))
+            })
+            .collect()
     }
 }
 
+type Category = String;
+
+type Input = (Vec<i64>, Vec<(Category, Category, Ranges)>);
+
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub enum AlmanacError {
+    DuplicateSource(Category),
+    DuplicateDestination(Category),
+    NoStart,
+    Disconnected(Category),
+    // The chain doesn't lead from the first to the second category
+    NoPath(Category, Category),
+    // The input is not an almanac, with nom's description of the error
+    Parse(String)
+}
+
+// The maps form a single chain of categories, each map being keyed by its
+// source category
 #[derive (Debug, Clone)]
 pub struct Almanac {
     seeds: Vec<i64>,
-    seed_to_soil: Ranges,
-    soil_to_fertilizer: Ranges,
-    fertilizer_to_water: Ranges,
-    water_to_light: Ranges,
-    light_to_temperature: Ranges,
-    temperature_to_humidity: Ranges,
-    humidity_to_location: Ranges,
+    maps: HashMap<Category, (Category, Ranges)>
+}
+
+impl Almanac {
+    pub fn new(seeds: Vec<i64>, maps: Vec<(Category, Category, Ranges)>)
+            -> Result<Self, AlmanacError> {
+        let mut sources = HashSet::new();
+        if let Some((s, _, _)) = maps.iter().find(|(s, _, _)| !sources.insert(s)) {
+            return Err (AlmanacError::DuplicateSource(s.clone()))
+        }
+
+        let mut destinations = HashSet::new();
+        if let Some((_, d, _)) = maps.iter().find(|(_, d, _)| !destinations.insert(d)) {
+            return Err (AlmanacError::DuplicateDestination(d.clone()))
+        }
+
+        let mut starts: Vec<&Category> = maps.iter()
+            .map(|(s, _, _)| s)
+            .filter(|s| !destinations.contains(s))
+            .collect();
+        starts.sort();
+        let start = match starts.as_slice() {
+            [] if maps.is_empty() => None,
+            [start] => Some ((*start).clone()),
+            [] => return Err (AlmanacError::NoStart),
+            [_, second, ..] => return Err (AlmanacError::Disconnected((*second).clone()))
+        };
+
+        let graph: HashMap<Category, (Category, Ranges)> = maps.into_iter()
+            .map(|(source, destination, ranges)| (source, (destination, ranges)))
+            .collect();
+
+        // With a single start and unique destinations, every map must be
+        // reachable from the start, otherwise some of them form a cycle
+        let mut visited = HashSet::new();
+        let mut current = start.as_ref();
+        while let Some(category) = current {
+            visited.insert(category);
+            current = graph.get(category).map(|(next, _)| next);
+        }
+        let mut unreachable: Vec<&Category> = graph.keys()
+            .filter(|c| !visited.contains(c))
+            .collect();
+        unreachable.sort();
+        if let Some(category) = unreachable.first() {
+            return Err (AlmanacError::Disconnected((*category).clone()))
+        }
+
+        Ok (Almanac { seeds, maps: graph })
+    }
+
+    // The maps in chain order
+    fn chain(&self) -> Vec<(&Category, &Category, &Ranges)> {
+        let destinations: HashSet<&Category> = self.maps.values().map(|(d, _)| d).collect();
+        let mut current = self.maps.keys().find(|c| !destinations.contains(c));
+        let mut chain = Vec::new();
+        while let Some(source) = current {
+            let (destination, ranges) = &self.maps[source];
+            chain.push((source, destination, ranges));
+            current = self.maps.get_key_value(destination).map(|(c, _)| c);
+        }
+        chain
+    }
+
+    pub fn validate(&self) -> Vec<(String, RangesReport)> {
+        self.chain().into_iter()
+            .map(|(source, destination, ranges)|
+                (format!("{source}-to-{destination}"), ranges.validate()))
+            .collect()
+    }
+
+    // The maps to apply in sequence to go from one category to another
+    pub fn path(&self, from: &str, to: &str) -> Option<Vec<&Ranges>> {
+        let mut path = Vec::new();
+        let mut current = from;
+        while current != to {
+            let (next, ranges) = self.maps.get(current)?;
+            path.push(ranges);
+            current = next;
+        }
+        Some (path)
+    }
+
+    pub fn function(&self, from: &str, to: &str) -> Option<PiecewiseAffine> {
+        let path = self.path(from, to)?;
+        let function = path.into_iter()
+            .map(PiecewiseAffine::from_ranges)
+            .fold(PiecewiseAffine::identity(), |f, g| f.then(&g));
+        Some (function)
+    }
+
+    pub fn map_value(&self, from: &str, to: &str, x: i64) -> Option<i64> {
+        let path = self.path(from, to)?;
+        Some (path.into_iter().fold(x, |x, ranges| ranges.map(x)))
+    }
+
+    // Every value of the source category sent to y
+    pub fn unmap_value(&self, from: &str, to: &str, y: i64) -> Option<Vec<i64>> {
+        let path = self.path(from, to)?;
+        let result = path.into_iter().rev().fold(vec![y], |ys, ranges| {
+            ys.into_iter().flat_map(|y| ranges.unmap(y)).collect()
+        });
+        Some (result)
+    }
+
+    // The intervals of the source category sent into the given intervals
+    pub fn unmap_intervals(&self, from: &str, to: &str, intervals: &Intervals)
+            -> Option<Intervals> {
+        let path = self.path(from, to)?;
+        let result = path.into_iter().rev()
+            .fold(intervals.clone(), |ints, ranges| ranges.unmap_intervals(&ints));
+        Some (result)
+    }
+
+    pub fn seed_to_location(&self) -> PiecewiseAffine {
+        self.function("seed", "location").unwrap()
+    }
 }
 
 
@@ -146,7 +464,7 @@ mod parser {
     use super::*;
 
     fn i64(input: &str) -> IResult<&str, i64> {
-        map(u32, |x| i64::try_from(x).unwrap())(input)
+        map(u32, i64::from)(input)
     }
 
     fn range(input: &str) -> IResult<&str, Range> {
@@ -168,82 +486,76 @@ mod parser {
         separated_list1(space1, i64)(input)
     }
 
-    fn almanac(input: &str) -> IResult<&str, Almanac> {
-        let (input, (
-                seeds,_,
-                seed_to_soil,_,
-                soil_to_fertilizer,_,
-                fertilizer_to_water,_,
-                water_to_light,_,
-                light_to_temperature,_,
-                temperature_to_humidity,_,
-                humidity_to_location)) =
-            tuple((
-                    preceded(pair(tag("seeds:"), multispace0), seeds),
-                    multispace1,
-                    preceded(pair(tag("seed-to-soil map:"), multispace1), ranges),
-                    multispace1,
-                    preceded(pair(tag("soil-to-fertilizer map:"), multispace1), ranges),
-                    multispace1,
-                    preceded(pair(tag("fertilizer-to-water map:"), multispace1), ranges),
-                    multispace1,
-                    preceded(pair(tag("water-to-light map:"), multispace1), ranges),
-                    multispace1,
-                    preceded(pair(tag("light-to-temperature map:"), multispace1), ranges),
-                    multispace1,
-                    preceded(pair(tag("temperature-to-humidity map:"), multispace1), ranges),
-                    multispace1,
-                    preceded(pair(tag("humidity-to-location map:"), multispace1), ranges)
-            ))(input)?;
-        let almanac = Almanac {
-            seeds,
-            seed_to_soil,
-            soil_to_fertilizer,
-            fertilizer_to_water,
-            water_to_light,
-            light_to_temperature,
-            temperature_to_humidity,
-            humidity_to_location
-         };
-         Ok ((input, almanac))
-    }
-
-    pub fn parse(input: &str) -> IResult<&str, Almanac> {
+    fn category(input: &str) -> IResult<&str, Category> {
+        map(alpha1, String::from)(input)
+    }
+
+    fn map_header(input: &str) -> IResult<&str, (Category, Category)> {
+        terminated(
+            separated_pair(category, tag("-to-"), category),
+            tag(" map:"))(input)
+    }
+
+    fn map_block(input: &str) -> IResult<&str, (Category, Category, Ranges)> {
+        map(
+            separated_pair(map_header, multispace1, ranges),
+            |((source, destination), ranges)| (source, destination, ranges)
+        )(input)
+    }
+
+    // The almanac is only checked by Almanac::new, once parsed
+    fn almanac(input: &str) -> IResult<&str, Input> {
+        separated_pair(
+            preceded(pair(tag("seeds:"), multispace0), seeds),
+            multispace1,
+            separated_list1(multispace1, map_block))(input)
+    }
+
+    pub fn parse(input: &str) -> IResult<&str, Input> {
         all_consuming(terminated(almanac, multispace0))(input)
     }
 }
 
 
 pub fn solve_part1(almanac: &Almanac) -> i64 {
-    let ids = almanac.seeds.clone();
-    let ids = almanac.seed_to_soil.map_vector(ids);
-    let ids = almanac.soil_to_fertilizer.map_vector(ids);
-    let ids = almanac.fertilizer_to_water.map_vector(ids);
-    let ids = almanac.water_to_light.map_vector(ids);
-    let ids = almanac.light_to_temperature.map_vector(ids);
-    let ids = almanac.temperature_to_humidity.map_vector(ids);
-    let ids = almanac.humidity_to_location.map_vector(ids);
+    let mut ids = almanac.seeds.clone();
+    for ranges in almanac.path("seed", "location").unwrap() {
+        ids = ranges.map_vector(ids);
+    }
     *ids.iter().min().unwrap()
 }
 
-fn vec_to_intervals(v: Vec<i64>) -> Vec<Interval> {
+fn vec_to_intervals(v: Vec<i64>) -> Intervals {
     v.chunks(2).map(|c| Interval(c[0], c[0] + c[1] - 1)).collect()
 }
 
+pub fn solve_part2_stepwise(almanac: &Almanac) -> i64 {
+    let mut ints = vec_to_intervals(almanac.seeds.clone());
+    for ranges in almanac.path("seed", "location").unwrap() {
+        ints = ranges.map_intervals(&ints);
+    }
+    ints.min().unwrap()
+}
+
 pub fn solve_part2(almanac: &Almanac) -> i64 {
-    let ints = vec_to_intervals(almanac.seeds.clone());
-    let ints = almanac.seed_to_soil.map_intervals(&ints);
-    let ints = almanac.soil_to_fertilizer.map_intervals(&ints);
-    let ints = almanac.fertilizer_to_water.map_intervals(&ints);
-    let ints = almanac.water_to_light.map_intervals(&ints);
-    let ints = almanac.light_to_temperature.map_intervals(&ints);
-    let ints = almanac.temperature_to_humidity.map_intervals(&ints);
-    let ints = almanac.humidity_to_location.map_intervals(&ints);
-    ints.iter().map(|interval| interval.0).min().unwrap()
+    let seeds = vec_to_intervals(almanac.seeds.clone());
+    let locations = almanac.seed_to_location().image(&seeds);
+    locations.min().unwrap()
+}
+
+// Reads an almanac that maps seeds to locations, as the puzzle needs
+pub fn load(input: &str) -> Result<Almanac, AlmanacError> {
+    let (_,(seeds, maps)) = parser::parse(input)
+        .map_err(|e| AlmanacError::Parse(e.to_string()))?;
+    let almanac = Almanac::new(seeds, maps)?;
+    if almanac.path("seed", "location").is_none() {
+        return Err (AlmanacError::NoPath(String::from("seed"), String::from("location")))
+    }
+    Ok (almanac)
 }
 
 pub fn solve(input: &str) -> (i64, i64) {
-    let (_,data) = parser::parse(input).unwrap();
+    let data = load(input).unwrap();
     (solve_part1(&data), solve_part2(&data))
 }
 
@@ -258,3 +570,156 @@ fn test_day5_input() {
     let solution = solve(&include_str!("../inputs/day5-input"));
     assert_eq!(solution, (486613012, 56931769));
 }
+
+#[test]
+fn test_day5_piecewise_affine() {
+    let almanac = load(include_str!("../inputs/day5-example")).unwrap();
+    let f = almanac.seed_to_location();
+    assert_eq!(f.apply(79), 82);
+    assert_eq!(f.apply(14), 43);
+    assert_eq!(f.apply(55), 86);
+    assert_eq!(f.apply(13), 35);
+
+    let g = f.inverse().unwrap();
+    for x in -10..110 {
+        assert_eq!(g.apply(f.apply(x)), x);
+    }
+
+    let seeds = f.preimage(&Intervals::from(Interval(i64::MIN, 45)));
+    for x in 0..100 {
+        let below = seeds.contains(&x);
+        assert_eq!(below, f.apply(x) <= 45);
+    }
+
+    let almanac = load(include_str!("../inputs/day5-input")).unwrap();
+    assert_eq!(solve_part2(&almanac), solve_part2_stepwise(&almanac));
+}
+
+#[test]
+fn test_day5_map_chains() {
+    let input = include_str!("../inputs/day5-example");
+    let almanac = load(input).unwrap();
+    assert_eq!(almanac.map_value("soil", "light", 81), Some(74));
+    assert_eq!(almanac.map_value("light", "soil", 74), None);
+    assert_eq!(almanac.function("soil", "light").unwrap().apply(81), 74);
+
+    // Reordering the blocks does not change the chain
+    let mut blocks: Vec<&str> = input.trim().split("\n\n").collect();
+    blocks[1..].reverse();
+    let reordered = load(&blocks.join("\n\n")).unwrap();
+    assert_eq!(solve_part1(&reordered), 35);
+    assert_eq!(solve_part2(&reordered), 46);
+
+    // Extra stages
+    let extended = format!("{}\n\nlocation-to-zone map:\n0 100 1000\n", input.trim());
+    let almanac = load(&extended).unwrap();
+    assert_eq!(almanac.map_value("seed", "zone", 79), Some(82));
+
+    let ranges = || Ranges(vec![]);
+    let cycle = vec![
+        (String::from("a"), String::from("b"), ranges()),
+        (String::from("b"), String::from("a"), ranges())];
+    assert_eq!(Almanac::new(vec![], cycle).err(), Some(AlmanacError::NoStart));
+    let split = vec![
+        (String::from("a"), String::from("b"), ranges()),
+        (String::from("c"), String::from("d"), ranges())];
+    assert_eq!(Almanac::new(vec![], split).err(),
+        Some(AlmanacError::Disconnected(String::from("c"))));
+    let fork = vec![
+        (String::from("a"), String::from("b"), ranges()),
+        (String::from("a"), String::from("c"), ranges())];
+    assert_eq!(Almanac::new(vec![], fork).err(),
+        Some(AlmanacError::DuplicateSource(String::from("a"))));
+
+    let disconnected = format!("{}\n\nzone-to-area map:\n0 100 1000\n", input.trim());
+    assert_eq!(load(&disconnected).err(),
+        Some(AlmanacError::Disconnected(String::from("zone"))));
+    assert_eq!(load("seeds: 1\n\na-to-b map:\n0 0 1\n").err(),
+        Some(AlmanacError::NoPath(String::from("seed"), String::from("location"))));
+    assert!(matches!(load("seeds: 1\n\nseed-to-location map:\n0 x 1\n"),
+        Err (AlmanacError::Parse(_))));
+}
+
+#[test]
+fn test_day5_reverse_lookup() {
+    let almanac = load(include_str!("../inputs/day5-example")).unwrap();
+    assert_eq!(almanac.unmap_value("seed", "location", 82), Some(vec![79]));
+    assert_eq!(almanac.unmap_value("seed", "soil", 50), Some(vec![98]));
+    assert_eq!(almanac.unmap_value("seed", "soil", 99), Some(vec![97]));
+    assert_eq!(almanac.unmap_value("seed", "soil", 10), Some(vec![10]));
+
+    let ranges = Ranges(vec![
+        Range { destination_start: 10, source_start: 0, length: 5 },
+        Range { destination_start: 20, source_start: 3, length: 5 }]);
+    assert_eq!(ranges.unmap(12), vec![2, 12]);
+    assert_eq!(ranges.unmap(3), vec![]);
+    assert_eq!(ranges.unmap(20), vec![20]);
+    assert_eq!(ranges.unmap(22), vec![5, 22]);
+    for y in 0..30 {
+        let sources = ranges.unmap_intervals(&Intervals::from(Interval(y, y)));
+        let values: Vec<i64> = sources.intervals().iter().flat_map(|i| i.0..=i.1).collect();
+        assert_eq!(values, ranges.unmap(y));
+    }
+
+    let location = Intervals::from(Interval(40, 60));
+    let seeds = almanac.unmap_intervals("seed", "location", &location).unwrap();
+    for x in 0..200 {
+        let inside = seeds.contains(&x);
+        let location = almanac.map_value("seed", "location", x).unwrap();
+        assert_eq!(inside, (40..=60).contains(&location));
+    }
+
+    // Cross-check part 2: no seed reaches a location below the answer
+    let almanac = load(include_str!("../inputs/day5-input")).unwrap();
+    let lowest = solve_part2(&almanac);
+    let reaches = |interval: Interval| {
+        let locations = Intervals::from(interval);
+        let sources = almanac.unmap_intervals("seed", "location", &locations).unwrap();
+        !vec_to_intervals(almanac.seeds.clone()).intersection(&sources).is_empty()
+    };
+    assert!(!reaches(Interval(0, lowest - 1)));
+    assert!(reaches(Interval(lowest, lowest)));
+}
+
+#[test]
+fn test_day5_validation() {
+    let almanac = load(include_str!("../inputs/day5-input")).unwrap();
+    let reports = almanac.validate();
+    assert_eq!(reports.len(), 7);
+    assert_eq!(reports[0].0, "seed-to-soil");
+    assert!(reports.iter().all(|(_, report)| report.is_valid()));
+
+    let ranges = Ranges(vec![
+        Range { destination_start: 100, source_start: 0, length: 10 },
+        Range { destination_start: 105, source_start: 20, length: 10 },
+        Range { destination_start: 200, source_start: 25, length: 10 }]);
+    let report = ranges.validate();
+    assert!(!report.is_valid());
+    assert_eq!(report.source_overlaps, vec![(1, 2, Interval(25, 29))]);
+    assert_eq!(report.destination_overlaps, vec![(0, 1, Interval(105, 109))]);
+    assert_eq!(report.identity_overlaps,
+        vec![(0, Interval(100, 109)), (1, Interval(105, 114)), (2, Interval(200, 209))]);
+    assert_eq!(report.gaps, vec![Interval(10, 19)]);
+    assert_eq!(report.to_string(), "\
+sources of ranges 1 and 2 overlap on [25, 29]
+destinations of ranges 0 and 1 overlap on [105, 109]
+destination of range 0 overlaps unmapped values on [100, 109]
+destination of range 1 overlaps unmapped values on [105, 114]
+destination of range 2 overlaps unmapped values on [200, 209]
+identity gap [10, 19]
+");
+
+    // 10..14 are sent onto 0..4, which also keep their own value
+    let ranges = Ranges(vec![Range { destination_start: 0, source_start: 10, length: 5 }]);
+    let report = ranges.validate();
+    assert!(!report.is_valid());
+    assert_eq!(report.identity_overlaps, vec![(0, Interval(0, 4))]);
+    assert!(PiecewiseAffine::from_ranges(&ranges).inverse().is_none());
+
+    // A permutation of 0..9 is valid
+    let ranges = Ranges(vec![
+        Range { destination_start: 5, source_start: 0, length: 5 },
+        Range { destination_start: 0, source_start: 5, length: 5 }]);
+    assert!(ranges.validate().is_valid());
+    assert!(PiecewiseAffine::from_ranges(&ranges).inverse().is_some());
+}
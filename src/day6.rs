@@ -1,3 +1,7 @@
+use num::{BigUint, Integer, ToPrimitive, integer::Roots};
+use crate::utils::intervals::*;
+
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Race {
     time: u64,
     distance: u64
@@ -37,7 +41,61 @@ mod parser {
     }
 }
 
+// Holding for i wins when i * (T - i) > D, i.e. when i lies strictly between
+// the roots (T ± sqrt(T² - 4D)) / 2. The floored square root only gives an
+// approximation of the bounds, which is then fixed by a few steps.
+fn winning_bounds<N>(time: N, distance: N) -> Option<(N, N)>
+        where N: Integer + Roots + Clone + From<u8> {
+    let wins = |i: &N| i.clone() * (time.clone() - i.clone()) > distance;
+    let square = time.clone() * time.clone();
+    let four_distance = N::from(4) * distance.clone();
+
+    if square <= four_distance {
+        return None
+    }
+
+    let root = (square - four_distance).sqrt();
+    let half = time.clone() / N::from(2);
+    let mut low = (time.clone() - root) / N::from(2);
+
+    while low <= half && !wins(&low) {
+        low = low + N::one();
+    }
+    while low > N::zero() && wins(&(low.clone() - N::one())) {
+        low = low - N::one();
+    }
+
+    if low > half {
+        None
+    }
+    else {
+        let high = time - low.clone();
+        Some ((low, high))
+    }
+}
+
+pub fn winning_interval(race: &Race) -> Option<Interval<u64>> {
+    let fits = race.time.checked_mul(race.time).is_some()
+        && race.distance.checked_mul(4).is_some();
+
+    let (low, high) = if fits {
+        winning_bounds(race.time, race.distance)?
+    }
+    else {
+        let (low, high) = winning_bounds(
+            BigUint::from(race.time),
+            BigUint::from(race.distance))?;
+        (low.to_u64().unwrap(), high.to_u64().unwrap())
+    };
+
+    Some (Interval(low, high))
+}
+
 fn win_count(race: &Race) -> u64 {
+    winning_interval(race).map_or(0, |interval| interval.cardinal())
+}
+
+fn _naive_win_count(race: &Race) -> u64 {
     let mut wins = 0;
 
     for i in 1..race.time {
@@ -92,3 +150,25 @@ fn test_day6_input() {
     let solution = solve(&include_str!("../inputs/day6-input"));
     assert_eq!(solution, (1084752, 28228952));
 }
+
+#[test]
+fn test_day6_random_races() {
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    let mut rng = StdRng::seed_from_u64(6);
+    for _ in 0..1000 {
+        let time = rng.gen_range(0..200);
+        let distance = rng.gen_range(0..time * time / 3 + 2);
+        let race = Race { time, distance };
+        assert_eq!(win_count(&race), _naive_win_count(&race), "{race:?}");
+    }
+}
+
+#[test]
+fn test_day6_big_race() {
+    let race = Race { time: u64::MAX, distance: u64::MAX };
+    let interval = winning_interval(&race).unwrap();
+    assert_eq!(interval, Interval(2, u64::MAX - 2));
+    let race = Race { time: 1 << 33, distance: u64::MAX };
+    assert_eq!(winning_interval(&race), Some(Interval(1 << 32, 1 << 32)));
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[derive (Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Direction { Left, Right }
@@ -9,7 +9,53 @@ type Neighbors = (Node, Node);
 
 type Input = (Vec<Direction>, Vec<(Node, Neighbors)>);
 
-type Graph = HashMap<String, (String, String)>;
+pub type Graph = HashMap<String, (String, String)>;
+
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub enum NodePattern {
+    Exact(String),
+    Suffix(String),
+    Glob(String)
+}
+
+impl NodePattern {
+    pub fn matches(&self, node: &str) -> bool {
+        match self {
+            NodePattern::Exact(name) => node == name,
+            NodePattern::Suffix(suffix) => node.ends_with(suffix.as_str()),
+            NodePattern::Glob(pattern) =>
+                glob_matches(pattern.as_bytes(), node.as_bytes())
+        }
+    }
+}
+
+// '*' matches any sequence of characters, '?' matches exactly one
+fn glob_matches(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) =>
+            (0..=text.len()).any(|i| glob_matches(rest, &text[i..])),
+        Some((c, rest)) => match text.split_first() {
+            Some((d, text_rest)) if *c == b'?' || c == d =>
+                glob_matches(rest, text_rest),
+            _ => false
+        }
+    }
+}
+
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub struct Walk {
+    pub steps: u64,
+    pub path: Vec<Node>
+}
+
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub enum WalkError {
+    // The node is not in the network
+    UnknownNode(Node),
+    // The walk loops forever without reaching the goal
+    Loop
+}
 
 mod parser {
     use nom::{
@@ -72,31 +118,61 @@ fn advance<'a>(graph: &'a Graph, node: &Node, direction: Direction)
     }
 }
 
-fn part1(path: &Vec<Direction>, graph: &Graph) -> u32 {
-    let mut count = 0;
-    let mut current : &Node = &String::from("AAA");
+// Follows the directions from start until a node matching goal is reached
+pub fn walk(path: &[Direction], graph: &Graph, start: &Node, goal: &NodePattern)
+        -> Result<Walk, WalkError> {
+    let mut visited = HashSet::new();
+    let mut current = start;
+    let mut nodes = vec![start.clone()];
 
-    loop {
-        for d in path {
-            current = advance(graph, current, *d);
-            count += 1;
-            if current == "ZZZ" {
-                return count;
-            }
+    for (i, d) in path.iter().enumerate().cycle() {
+        if !graph.contains_key(current) {
+            return Err (WalkError::UnknownNode(current.clone()));
+        }
+        if !visited.insert((current, i)) {
+            return Err (WalkError::Loop);
+        }
+        current = advance(graph, current, *d);
+        nodes.push(current.clone());
+        if goal.matches(current) {
+            let steps = nodes.len() as u64 - 1;
+            return Ok (Walk { steps, path: nodes });
         }
     }
+
+    // Only reached with no direction at all
+    Err (WalkError::Loop)
 }
 
-fn starting_nodes(graph: &Graph) -> Vec<&Node> {
-    graph.iter()
-        .map(|(name,_)| name)
-        .filter(|name| name.ends_with('A'))
+// Walks from every node matching start, in name order
+pub fn walk_all(
+        path: &[Direction],
+        graph: &Graph,
+        start: &NodePattern,
+        goal: &NodePattern) -> Vec<(Node, Result<Walk, WalkError>)> {
+    matching_nodes(graph, start).into_iter()
+        .map(|node| (node.clone(), walk(path, graph, node, goal)))
         .collect()
 }
 
-fn _naive_part2(path: &Vec<Direction>, graph: &Graph) -> u32 {
+fn part1(path: &[Direction], graph: &Graph) -> u32 {
+    let goal = NodePattern::Exact(String::from("ZZZ"));
+    let walk = walk(path, graph, &String::from("AAA"), &goal).unwrap();
+    walk.steps as u32
+}
+
+fn matching_nodes<'a>(graph: &'a Graph, pattern: &NodePattern) -> Vec<&'a Node> {
+    let mut nodes: Vec<&Node> = graph.keys()
+        .filter(|name| pattern.matches(name))
+        .collect();
+    nodes.sort();
+    nodes
+}
+
+fn _naive_part2(path: &[Direction], graph: &Graph) -> u32 {
     let mut count = 0;
-    let mut current : Vec<&Node> = starting_nodes(graph);
+    let start = NodePattern::Suffix(String::from("A"));
+    let mut current : Vec<&Node> = matching_nodes(graph, &start);
 
     loop {
         for d in path {
@@ -112,45 +188,34 @@ fn _naive_part2(path: &Vec<Direction>, graph: &Graph) -> u32 {
     }
 }
 
-fn part2(path: &Vec<Direction>, graph: &Graph) -> u64 {
-    let sources: Vec<&Node> = starting_nodes(graph);
-    let mut lengths: Vec<u64> = Vec::new();
-
-    for mut node in sources {
-        let mut count = 0;
-    
-        'outer: loop {
-            for d in path {
-                node = advance(graph, node, *d);
-                count += 1;
-                if node.ends_with('Z') {
-                    lengths.push(count);
-                    break 'outer;
-                }
-            }
-        }
-    }
+fn part2(path: &[Direction], graph: &Graph) -> u64 {
+    let start = NodePattern::Suffix(String::from("A"));
+    let goal = NodePattern::Suffix(String::from("Z"));
 
-    lengths.into_iter().fold(1u64, num::integer::lcm)
+    walk_all(path, graph, &start, &goal).into_iter()
+        .map(|(_, walk)| walk.unwrap().steps)
+        .fold(1u64, num::integer::lcm)
 }
 
-pub fn solve_part1(input: &str) -> u32 {
+pub fn parse_network(input: &str) -> (Vec<Direction>, Graph) {
     let (_,(path, neighbors)) = parser::parse(input).unwrap();
-    let graph = build_graph(neighbors);
+    (path, build_graph(neighbors))
+}
+
+pub fn solve_part1(input: &str) -> u32 {
+    let (path, graph) = parse_network(input);
 
     part1(&path, &graph)
 }
 
 pub fn solve_part2(input: &str) -> u64 {
-    let (_,(path, neighbors)) = parser::parse(input).unwrap();
-    let graph = build_graph(neighbors);
+    let (path, graph) = parse_network(input);
 
     part2(&path, &graph)
 }
 
 pub fn solve(input: &str) -> (u32, u64) {
-    let (_,(path, neighbors)) = parser::parse(input).unwrap();
-    let graph = build_graph(neighbors);
+    let (path, graph) = parse_network(input);
 
     (part1(&path, &graph), part2(&path, &graph))
 }
@@ -178,3 +243,26 @@ fn day8_input() {
     let solution = solve(&include_str!("../inputs/day8-input"));
     assert_eq!(solution, (22357, 10371555451871));
 }
+
+#[test]
+fn day8_walk_queries() {
+    let (path, graph) = parse_network(include_str!("../inputs/day8-example3"));
+    let goal = NodePattern::Glob(String::from("2?Z"));
+    let found = walk(&path, &graph, &String::from("22A"), &goal).unwrap();
+    assert_eq!(found.steps, 3);
+    assert_eq!(found.path, vec!["22A", "22B", "22C", "22Z"]);
+
+    let start = NodePattern::Glob(String::from("*A"));
+    let goal = NodePattern::Exact(String::from("ZZZ"));
+    let walks = walk_all(&path, &graph, &start, &goal);
+    assert_eq!(walks, vec![
+        (String::from("11A"), Err (WalkError::Loop)),
+        (String::from("22A"), Err (WalkError::Loop))]);
+
+    let goal = NodePattern::Suffix(String::from("Z"));
+    assert_eq!(walk(&path, &graph, &String::from("QQQ"), &goal),
+        Err (WalkError::UnknownNode(String::from("QQQ"))));
+    let (path, graph) = parse_network("L\n\nAAA = (BBB, BBB)\nBBB = (CCC, AAA)");
+    assert_eq!(walk(&path, &graph, &String::from("AAA"), &goal),
+        Err (WalkError::UnknownNode(String::from("CCC"))));
+}
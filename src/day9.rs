@@ -1,6 +1,7 @@
-use std::collections::VecDeque;
+use std::{fmt, collections::VecDeque};
+use num::{BigInt, BigRational, Zero, One, ToPrimitive};
 
-type Series = VecDeque<i32>;
+type Series = VecDeque<i64>;
 
 type Input = Vec<Series>;
 
@@ -16,7 +17,7 @@ mod parser {
     use super::*;
 
     pub fn series(input: &str) -> IResult<&str, Series> {
-        map(separated_list1(space1, i32), Series::from)(input)
+        map(separated_list1(space1, i64), Series::from)(input)
     }
 
     pub fn parse(input: &str) -> IResult<&str, Input> {
@@ -27,98 +28,182 @@ mod parser {
     }
 }
 
-fn derive(v: &Series) -> Series {
+// None on overflow
+fn derive(v: &Series) -> Option<Series> {
     let mut result = VecDeque::new();
     let mut it = v.iter();
 
     if let Some(mut prev) = it.next() {
         for cur in it {
-            result.push_back(cur - prev);
+            result.push_back(cur.checked_sub(*prev)?);
             prev = cur;
         }
     }
 
-    result
+    Some (result)
 }
 
-fn extrapolate_right(v1: &mut Series, v2: &Series) {
-    let x = *v1.back().unwrap() + v2.back().unwrap();
-    v1.push_back(x);
+fn is_zero(v: &Series) -> bool {
+    v.iter().all(|&x| x == 0)
 }
 
-fn extrapolate_left(v1: &mut Series, v2: &Series) {
-    let x = *v1.front().unwrap() - v2.front().unwrap();
-    v1.push_front(x);
+// The rows of successive differences, down to the first all-zero row.
+// Operations return None when a value overflows, leaving the table unchanged.
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub struct DifferenceTable {
+    rows: Vec<Series>
 }
 
-fn is_zero(v: &Series) -> bool {
-    v.iter().all(|&x| x == 0)
-}
+impl DifferenceTable {
+    pub fn new(v: &Series) -> Option<Self> {
+        let mut rows: Vec<Series> = vec![v.clone()];
+        while !is_zero(rows.last().unwrap()) {
+            let w = derive(rows.last().unwrap())?;
+            rows.push(w);
+        }
+        Some (DifferenceTable { rows })
+    }
+
+    pub fn series(&self) -> &Series {
+        &self.rows[0]
+    }
+
+    // The next value of every row, from the bottom one up, given the value
+    // at the edge of each row and how to combine it with the one below
+    fn next_column<F>(&self, edge: F, combine: fn(i64, i64) -> Option<i64>)
+            -> Option<Vec<i64>>
+            where F: Fn(&Series) -> Option<&i64> {
+        let mut column = vec![0];
+        for row in self.rows.iter().rev().skip(1) {
+            let below = *column.last().unwrap();
+            column.push(combine(*edge(row).unwrap_or(&0), below)?);
+        }
+        column.reverse();
+        Some (column)
+    }
 
-fn solve_vector_right(v: &Series) -> i32 {
-    let mut all: Vec<Series> = vec![v.clone()];
-    loop {
-        let w = derive(all.last().unwrap());
-        if is_zero(&w) {
-            break;
+    pub fn extend_right(&mut self, steps: usize) -> Option<()> {
+        for _ in 0..steps {
+            let column = self.next_column(Series::back, i64::checked_add)?;
+            for (row, x) in self.rows.iter_mut().zip(column) {
+                row.push_back(x);
+            }
         }
-        all.push(w);
+        Some (())
     }
 
-    let mut it = all.iter_mut().rev();
-    if let Some (mut w) = it.next() {
-        for v in it {
-            extrapolate_right(v, w);
-            w = v;
+    pub fn extend_left(&mut self, steps: usize) -> Option<()> {
+        for _ in 0..steps {
+            let column = self.next_column(Series::front, i64::checked_sub)?;
+            for (row, x) in self.rows.iter_mut().zip(column) {
+                row.push_front(x);
+            }
         }
+        Some (())
     }
+}
 
-    return *all.first().unwrap_or(&Series::new()).back().unwrap_or(&0)
+impl fmt::Display for DifferenceTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.rows.iter()
+            .flatten()
+            .map(|x| x.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let pitch = (width + 2) / 2 * 2;
+
+        for (depth, row) in self.rows.iter().enumerate() {
+            let cells: Vec<String> =
+                row.iter().map(|x| format!("{x:>width$}")).collect();
+            let indent = " ".repeat(depth * pitch / 2);
+            let separator = " ".repeat(pitch - width);
+            writeln!(f, "{indent}{}", cells.join(&separator))?;
+        }
+        Ok (())
+    }
 }
 
-fn solve_vector_left(v: &Series) -> i32 {
-    let mut all: Vec<Series> = vec![v.clone()];
-    loop {
-        let w = derive(all.last().unwrap());
-        if is_zero(&w) {
-            break;
+// Polynomial in Newton forward form: p(x) = sum_k d_k * binomial(x, k),
+// where d_k is the first element of the k-th difference row
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub struct Polynomial {
+    differences: Vec<BigInt>,
+    converged: bool
+}
+
+impl Polynomial {
+    pub fn fit(series: &[i64]) -> Self {
+        let mut row: Vec<BigInt> = series.iter().map(|&x| BigInt::from(x)).collect();
+        let mut differences = Vec::new();
+
+        while !row.iter().all(Zero::is_zero) {
+            differences.push(row[0].clone());
+            row = row.windows(2).map(|w| &w[1] - &w[0]).collect();
         }
-        all.push(w);
+
+        // The empty row is trivially zero, but it only tells that we ran out
+        // of values, not that the series is actually polynomial
+        let converged = !row.is_empty();
+        Polynomial { differences, converged }
+    }
+
+    // None for the zero polynomial
+    pub fn degree(&self) -> Option<usize> {
+        self.differences.len().checked_sub(1)
+    }
+
+    // Whether the series reached an all-zero difference row, i.e. whether the
+    // series contains enough values to determine the polynomial
+    pub fn converged(&self) -> bool {
+        self.converged
     }
 
-    let mut it = all.iter_mut().rev();
-    if let Some (mut w) = it.next() {
-        for v in it {
-            extrapolate_left(v, w);
-            w = v;
+    pub fn evaluate(&self, x: i64) -> BigInt {
+        let x = BigRational::from_integer(BigInt::from(x));
+        let mut binomial = BigRational::one();
+        let mut result = BigRational::zero();
+
+        for (k, d) in self.differences.iter().enumerate() {
+            result += &binomial * BigRational::from_integer(d.clone());
+            let k = BigRational::from_integer(BigInt::from(k));
+            binomial = binomial * (&x - &k) / (k + BigRational::one());
         }
+
+        assert!(result.is_integer());
+        result.to_integer()
     }
 
-    return *all.first().unwrap_or(&Series::new()).front().unwrap_or(&0)
+    pub fn evaluate_i64(&self, x: i64) -> Option<i64> {
+        self.evaluate(x).to_i64()
+    }
 }
 
 
-fn part1(input: &Input) -> i32 {
-    let mut result = 0;
+fn part1(input: &Input) -> i64 {
+    let mut result: i64 = 0;
 
     for v in input {
-        result += solve_vector_right(v);
+        let mut table = DifferenceTable::new(v).expect("overflow");
+        table.extend_right(1).expect("overflow");
+        result = result.checked_add(*table.series().back().unwrap()).expect("overflow");
     }
 
     result
 }
 
-fn part2(input: &Input) -> i32 {
-    let mut result = 0;
+fn part2(input: &Input) -> i64 {
+    let mut result: i64 = 0;
 
     for v in input {
-        result += solve_vector_left(v);
+        let mut table = DifferenceTable::new(v).expect("overflow");
+        table.extend_left(1).expect("overflow");
+        result = result.checked_add(*table.series().front().unwrap()).expect("overflow");
     }
 
     result
 }
 
-pub fn solve(input: &str) -> (i32, i32) {
+pub fn solve(input: &str) -> (i64, i64) {
     let (_,input) = parser::parse(input).unwrap();
 
     (part1(&input), part2(&input))
@@ -135,3 +220,63 @@ fn day9_input() {
   let solution = solve(&include_str!("../inputs/day9-input"));
   assert_eq!(solution, (1882395907, 1005));
 }
+
+#[test]
+fn day9_polynomial() {
+    let p = Polynomial::fit(&[10, 13, 16, 21, 30, 45]);
+    assert_eq!(p.degree(), Some(3));
+    assert!(p.converged());
+    assert_eq!(p.evaluate_i64(6), Some(68));
+    assert_eq!(p.evaluate_i64(-1), Some(5));
+    assert_eq!(p.evaluate_i64(-2), Some(-4));
+
+    let p = Polynomial::fit(&[1, 2, 4, 8, 16]);
+    assert!(!p.converged());
+
+    let p = Polynomial::fit(&[0, 0, 0]);
+    assert_eq!(p.degree(), None);
+    assert_eq!(p.evaluate_i64(1000), Some(0));
+
+    let (_,input) = parser::parse(include_str!("../inputs/day9-input")).unwrap();
+    for v in &input {
+        let series: Vec<i64> = v.iter().copied().collect();
+        let p = Polynomial::fit(&series);
+        let n = series.len() as i64;
+        let mut table = DifferenceTable::new(v).unwrap();
+        table.extend_left(3).unwrap();
+        table.extend_right(3).unwrap();
+        for (i, &x) in table.series().iter().enumerate() {
+            assert_eq!(p.evaluate_i64(i as i64 - 3), Some(x));
+        }
+        assert_eq!(table.series().len() as i64, n + 6);
+    }
+}
+
+#[test]
+fn day9_difference_table() {
+    let mut table = DifferenceTable::new(&Series::from([10, 13, 16, 21, 30, 45])).unwrap();
+    table.extend_left(1).unwrap();
+    let expected = [
+        " 5  10  13  16  21  30  45",
+        "   5   3   3   5   9  15",
+        "    -2   0   2   4   6",
+        "       2   2   2   2",
+        "         0   0   0",
+    ];
+    assert_eq!(table.to_string(), expected.map(|line| line.to_owned() + "\n").concat());
+}
+
+#[test]
+fn day9_overflow() {
+    assert!(DifferenceTable::new(&Series::from([i64::MIN, i64::MAX])).is_none());
+
+    let mut table = DifferenceTable::new(&Series::from([i64::MAX - 2, i64::MAX - 1])).unwrap();
+    assert_eq!(table.extend_right(1), Some (()));
+    assert_eq!(table.series().back(), Some(&i64::MAX));
+    let before = table.clone();
+    assert_eq!(table.extend_right(1), None);
+    assert_eq!(table, before);
+
+    let mut table = DifferenceTable::new(&Series::from([i64::MIN, i64::MIN + 1])).unwrap();
+    assert_eq!(table.extend_left(1), None);
+}
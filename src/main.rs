@@ -3,6 +3,8 @@ pub mod utils {
     pub mod positions;
     pub mod grids;
     pub mod intervals;
+    pub mod boxes;
+    pub mod polygon;
 }
 
 pub mod day1;
@@ -0,0 +1,266 @@
+use std::hash::{Hash, Hasher};
+use std::ops::{Index, IndexMut};
+use num::{PrimInt, ToPrimitive, CheckedAdd, One};
+use super::intervals::*;
+
+// Cartesian product of N closed intervals
+#[derive(Debug, Clone, Copy)]
+pub struct HyperRect<T, const N: usize> (pub [Interval<T>; N]);
+
+// Equality and hashing are written out because comparing intervals needs
+// T: PartialOrd, which derive wouldn't require
+impl<T: PartialOrd, const N: usize> PartialEq for HyperRect<T, N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<T: PartialOrd, const N: usize> Eq for HyperRect<T, N> {}
+
+impl<T: PartialOrd + Hash, const N: usize> Hash for HyperRect<T, N> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl<T, const N: usize> Index<usize> for HyperRect<T, N> {
+    type Output = Interval<T>;
+
+    fn index(&self, axis: usize) -> &Interval<T> {
+        &self.0[axis]
+    }
+}
+
+impl<T, const N: usize> IndexMut<usize> for HyperRect<T, N> {
+    fn index_mut(&mut self, axis: usize) -> &mut Interval<T> {
+        &mut self.0[axis]
+    }
+}
+
+// Cartesian product of intervals whose dimension is only known at runtime
+#[derive(Debug, Clone)]
+pub struct DynRect<T> (pub Vec<Interval<T>>);
+
+impl<T: PartialOrd> PartialEq for DynRect<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<T: PartialOrd> Eq for DynRect<T> {}
+
+impl<T: PartialOrd + Hash> Hash for DynRect<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl<T> Index<usize> for DynRect<T> {
+    type Output = Interval<T>;
+
+    fn index(&self, axis: usize) -> &Interval<T> {
+        &self.0[axis]
+    }
+}
+
+impl<T> IndexMut<usize> for DynRect<T> {
+    fn index_mut(&mut self, axis: usize) -> &mut Interval<T> {
+        &mut self.0[axis]
+    }
+}
+
+// Operations shared by boxes, whatever the way their axes are stored
+pub trait Rect: Clone {
+    type Value: PrimInt;
+
+    fn axes(&self) -> &[Interval<Self::Value>];
+    fn axes_mut(&mut self) -> &mut [Interval<Self::Value>];
+
+    fn is_empty(&self) -> bool {
+        self.axes().iter().any(Interval::is_empty)
+    }
+
+    fn contains(&self, point: &[Self::Value]) -> bool {
+        self.axes().iter().zip(point).all(|(itv, x)| itv.contains(x))
+    }
+
+    fn volume(&self) -> u128 {
+        if self.is_empty() {
+            return 0
+        }
+        self.axes().iter()
+            .map(|itv| (itv.1.to_i128().unwrap() - itv.0.to_i128().unwrap() + 1) as u128)
+            .product()
+    }
+
+    fn intersection(&self, other: &Self) -> Option<Self> {
+        let mut result = self.clone();
+        for (itv, other) in result.axes_mut().iter_mut().zip(other.axes()) {
+            *itv = itv.intersection(other)?;
+        }
+        Some (result)
+    }
+
+    // Smallest box containing both boxes
+    fn hull(&self, other: &Self) -> Self {
+        let mut result = self.clone();
+        for (itv, other) in result.axes_mut().iter_mut().zip(other.axes()) {
+            *itv = Interval(itv.0.min(other.0), itv.1.max(other.1));
+        }
+        result
+    }
+
+    fn with_axis(&self, axis: usize, interval: Option<Interval<Self::Value>>) -> Option<Self> {
+        let mut result = self.clone();
+        result.axes_mut()[axis] = interval?;
+        if result.is_empty() { None } else { Some (result) }
+    }
+
+    // Splits into the parts below x and from x on along the given axis
+    fn split_before(&self, axis: usize, x: Self::Value) -> (Option<Self>, Option<Self>) {
+        let (below, above) = self.axes()[axis].split_at(x);
+        (self.with_axis(axis, below), self.with_axis(axis, above))
+    }
+
+    // Splits into the parts up to x and above x along the given axis
+    fn split_after(&self, axis: usize, x: Self::Value) -> (Option<Self>, Option<Self>) {
+        match x.checked_add(&Self::Value::one()) {
+            Some(y) => self.split_before(axis, y),
+            None => (self.with_axis(axis, Some (self.axes()[axis])), None)
+        }
+    }
+
+    // Disjoint boxes covering self minus other
+    fn difference(&self, other: &Self) -> Vec<Self> {
+        if self.intersection(other).is_none() {
+            return vec![self.clone()]
+        }
+
+        let mut result = Vec::new();
+        let mut rest = self.clone();
+        for (axis, itv) in other.axes().iter().enumerate() {
+            let (below, middle) = rest.split_before(axis, itv.0);
+            let (middle, above) = match middle {
+                Some(middle) => middle.split_after(axis, itv.1),
+                None => (None, None)
+            };
+            result.extend(below);
+            result.extend(above);
+            match middle {
+                Some(middle) => rest = middle,
+                None => break
+            }
+        }
+        result
+    }
+}
+
+impl<T: PrimInt, const N: usize> Rect for HyperRect<T, N> {
+    type Value = T;
+
+    fn axes(&self) -> &[Interval<T>] {
+        &self.0
+    }
+
+    fn axes_mut(&mut self) -> &mut [Interval<T>] {
+        &mut self.0
+    }
+}
+
+impl<T: PrimInt> Rect for DynRect<T> {
+    type Value = T;
+
+    fn axes(&self) -> &[Interval<T>] {
+        &self.0
+    }
+
+    fn axes_mut(&mut self) -> &mut [Interval<T>] {
+        &mut self.0
+    }
+}
+
+// Union of pairwise disjoint boxes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoxSet<B> (Vec<B>);
+
+impl<B> Default for BoxSet<B> {
+    fn default() -> Self {
+        BoxSet(Vec::new())
+    }
+}
+
+impl<B: Rect> FromIterator<B> for BoxSet<B> {
+    fn from_iter<I: IntoIterator<Item = B>>(iter: I) -> Self {
+        let mut set = BoxSet::default();
+        for b in iter {
+            set.insert(&b);
+        }
+        set
+    }
+}
+
+impl<B: Rect> BoxSet<B> {
+    pub fn boxes(&self) -> &[B] {
+        &self.0
+    }
+
+    // Adds the part of the box not already covered
+    pub fn insert(&mut self, b: &B) {
+        let mut pieces: Vec<B> =
+            if b.is_empty() { Vec::new() } else { vec![b.clone()] };
+        for existing in &self.0 {
+            pieces = pieces.iter().flat_map(|p| p.difference(existing)).collect();
+        }
+        self.0.extend(pieces);
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        let mut result = self.clone();
+        for b in &other.0 {
+            result.insert(b);
+        }
+        result
+    }
+
+    pub fn contains(&self, point: &[B::Value]) -> bool {
+        self.0.iter().any(|b| b.contains(point))
+    }
+
+    pub fn volume(&self) -> u128 {
+        self.0.iter().map(Rect::volume).sum()
+    }
+}
+
+#[test]
+fn box_set_volume() {
+    let a = HyperRect([Interval(0, 9), Interval(0, 9), Interval(0, 9)]);
+    let b = HyperRect([Interval(5, 14), Interval(5, 14), Interval(5, 14)]);
+    assert_eq!(a.volume(), 1000);
+    assert_eq!(a.intersection(&b).map(|i| i.volume()), Some(125));
+    assert_eq!(a.difference(&b).iter().map(Rect::volume).sum::<u128>(), 875);
+
+    let set: BoxSet<HyperRect<i32, 3>> = [a, b, a].into_iter().collect();
+    assert_eq!(set.volume(), 1875);
+    for x in -1..16 {
+        let p = [x, x, 7];
+        assert_eq!(set.contains(&p), a.contains(&p) || b.contains(&p));
+        let covering = set.boxes().iter().filter(|b| b.contains(&p)).count();
+        assert!(covering <= 1);
+    }
+
+    let (below, above) = a.split_before(1, 3);
+    assert_eq!(below, Some(HyperRect([Interval(0, 9), Interval(0, 2), Interval(0, 9)])));
+    assert_eq!(above, Some(HyperRect([Interval(0, 9), Interval(3, 9), Interval(0, 9)])));
+    assert_eq!(a.split_after(0, 9), (Some(a), None));
+}
+
+#[test]
+fn dyn_rect_matches_hyper_rect() {
+    let a = HyperRect([Interval(0u32, 9), Interval(3, 7)]);
+    let b = HyperRect([Interval(4u32, 20), Interval(0, 5)]);
+    let (c, d) = (DynRect(a.0.to_vec()), DynRect(b.0.to_vec()));
+    assert_eq!(a.volume(), c.volume());
+    assert_eq!(a.intersection(&b).map(|r| r.0.to_vec()), c.intersection(&d).map(|r| r.0));
+    let set: BoxSet<DynRect<u32>> = [c, d].into_iter().collect();
+    assert_eq!(set.volume(), 50 + 102 - 18);
+}
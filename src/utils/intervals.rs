@@ -1,43 +1,283 @@
+use std::hash::{Hash, Hasher};
 use std::ops::{Add,Sub};
+use num::PrimInt;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+// Closed interval [a, b], empty when a > b. All empty intervals are equal,
+// whatever their bounds.
+#[derive(Debug, Clone, Copy)]
 pub struct Interval<T> (pub T, pub T);
 
+impl<T: PartialOrd> PartialEq for Interval<T> {
+    fn eq(&self, other: &Self) -> bool {
+        let (empty, other_empty) = (self.0 > self.1, other.0 > other.1);
+        (empty && other_empty) || (!empty && !other_empty && self.0 == other.0 && self.1 == other.1)
+    }
+}
+
+impl<T: PartialOrd> Eq for Interval<T> {}
+
+impl<T: PartialOrd + Hash> Hash for Interval<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let empty = self.0 > self.1;
+        empty.hash(state);
+        if !empty {
+            self.0.hash(state);
+            self.1.hash(state);
+        }
+    }
+}
+
 impl<T> Interval<T> {
     pub fn cardinal(&self) -> T
-            where T: Copy + Sub<T, Output=T> + Add<T, Output=T> + From<u8> {
-        self.1 - self.0 + T::from(1)
+            where T: Copy + Ord + Sub<T, Output=T> + Add<T, Output=T> + From<u8> {
+        if self.is_empty() {
+            T::from(0)
+        }
+        else {
+            self.1 - self.0 + T::from(1)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool where T: Ord {
+        self.0 > self.1
+    }
+
+    pub fn contains(&self, x: &T) -> bool where T: Ord {
+        self.0 <= *x && *x <= self.1
+    }
+
+    pub fn intersection(&self, other: &Self) -> Option<Self> where T: Copy + Ord {
+        let inter = Interval(self.0.max(other.0), self.1.min(other.1));
+        if inter.is_empty() { None } else { Some (inter) }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum SplitResult { Below, Above }
+impl<T: PrimInt> Interval<T> {
+    pub fn empty() -> Self {
+        Interval(T::max_value(), T::min_value())
+    }
 
-impl<T> Interval<T> {
-    pub fn split_before(&self, x: T) -> Vec<(SplitResult,Self)>
-            where  T: Copy + Ord + Sub<T, Output=T> + From<u8> {
-        if self.0 >= x {
-            vec![(SplitResult::Above,*self)]
-        } else if self.1 < x {
-            vec![(SplitResult::Below,*self)]
-        } else {
-            vec![
-                (SplitResult::Below, Interval(self.0, x - T::from(1))),
-                (SplitResult::Above, Interval(x, self.1))]
+    // [a, b], normalised to the empty interval when a > b
+    pub fn closed(a: T, b: T) -> Self {
+        if a > b { Self::empty() } else { Interval(a, b) }
+    }
+
+    // [a, b)
+    pub fn half_open(a: T, b: T) -> Self {
+        match b.checked_sub(&T::one()) {
+            Some(end) => Self::closed(a, end),
+            None => Self::empty()
+        }
+    }
+
+    fn non_empty(self) -> Option<Self> {
+        if self.is_empty() { None } else { Some (self) }
+    }
+
+    // Splits into the values below x and the values from x on
+    pub fn split_at(&self, x: T) -> (Option<Self>, Option<Self>) {
+        let below = x.checked_sub(&T::one())
+            .and_then(|y| Interval(self.0, self.1.min(y)).non_empty());
+        let above = Interval(self.0.max(x), self.1).non_empty();
+        (below, above)
+    }
+}
+
+impl<T: Copy + Add<T, Output=T>> Add<T> for Interval<T> {
+    type Output = Interval<T>;
+
+    fn add(self, x: T) -> Interval<T> {
+        Interval(self.0 + x, self.1 + x)
+    }
+}
+
+
+// Set of values represented as sorted, disjoint and non-adjacent intervals
+#[derive(Debug, Clone)]
+pub struct IntervalSet<T> (Vec<Interval<T>>);
+
+// Written out because comparing intervals needs T: PartialOrd
+impl<T: PartialOrd> PartialEq for IntervalSet<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<T: PartialOrd> Eq for IntervalSet<T> {}
+
+impl<T: PartialOrd + Hash> Hash for IntervalSet<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl<T> Default for IntervalSet<T> {
+    fn default() -> Self {
+        IntervalSet(Vec::new())
+    }
+}
+
+impl<T: PrimInt> From<Interval<T>> for IntervalSet<T> {
+    fn from(interval: Interval<T>) -> Self {
+        IntervalSet::new([interval])
+    }
+}
+
+impl<T: PrimInt> FromIterator<Interval<T>> for IntervalSet<T> {
+    fn from_iter<I: IntoIterator<Item = Interval<T>>>(iter: I) -> Self {
+        IntervalSet::new(iter)
+    }
+}
+
+impl<T: PrimInt> IntervalSet<T> {
+    pub fn new<I: IntoIterator<Item = Interval<T>>>(intervals: I) -> Self {
+        let mut intervals: Vec<Interval<T>> = intervals.into_iter()
+            .filter(|itv| !itv.is_empty())
+            .collect();
+        intervals.sort_by_key(|itv| itv.0);
+
+        let mut result: Vec<Interval<T>> = Vec::new();
+        for itv in intervals {
+            match result.last_mut() {
+                Some(last) if last.1.checked_add(&T::one()).is_none_or(|x| x >= itv.0) =>
+                    last.1 = last.1.max(itv.1),
+                _ => result.push(itv)
+            }
+        }
+        IntervalSet(result)
+    }
+
+    pub fn intervals(&self) -> &[Interval<T>] {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn min(&self) -> Option<T> {
+        self.0.first().map(|itv| itv.0)
+    }
+
+    pub fn max(&self) -> Option<T> {
+        self.0.last().map(|itv| itv.1)
+    }
+
+    pub fn contains(&self, x: &T) -> bool {
+        let i = self.0.partition_point(|itv| itv.1 < *x);
+        self.0.get(i).is_some_and(|itv| itv.contains(x))
+    }
+
+    pub fn cardinal(&self) -> T {
+        self.0.iter().fold(T::zero(), |acc, itv| acc + (itv.1 - itv.0 + T::one()))
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        IntervalSet::new(self.0.iter().chain(other.0.iter()).copied())
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut result = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.0.len() && j < other.0.len() {
+            let (a, b) = (self.0[i], other.0[j]);
+            result.extend(a.intersection(&b));
+            if a.1 < b.1 { i += 1 } else { j += 1 }
         }
+        IntervalSet(result)
     }
 
-    pub fn split_after(&self, x: T) -> Vec<(SplitResult, Self)>
-            where  T: Copy + Ord + Add<T, Output=T> + From<u8> {
-        if self.0 > x {
-            vec![(SplitResult::Above,*self)]
-        } else if self.1 <= x {
-            vec![(SplitResult::Below,*self)]
-        } else {
-            vec![
-                (SplitResult::Below, Interval(self.0, x)),
-                (SplitResult::Above, Interval(x + T::from(1), self.1))]
+    // The values of bounds that are not in the set
+    pub fn complement(&self, bounds: Interval<T>) -> Self {
+        let mut result = Vec::new();
+        let mut start = Some (bounds.0);
+        for itv in &self.0 {
+            match start {
+                Some(s) if itv.0 > s =>
+                    result.push(Interval(s, (itv.0 - T::one()).min(bounds.1))),
+                None => break,
+                _ => ()
+            }
+            start = itv.1.checked_add(&T::one()).map(|x| x.max(bounds.0));
+        }
+        if let Some(s) = start {
+            result.push(Interval(s, bounds.1));
         }
+        IntervalSet::new(result)
     }
 
-}
\ No newline at end of file
+    pub fn difference(&self, other: &Self) -> Self {
+        match (self.min(), self.max()) {
+            (Some(min), Some(max)) =>
+                self.intersection(&other.complement(Interval(min, max))),
+            _ => IntervalSet::default()
+        }
+    }
+
+    pub fn shift(&self, offset: T) -> Self {
+        IntervalSet(self.0.iter().map(|&itv| itv + offset).collect())
+    }
+}
+
+#[test]
+fn interval_set_algebra() {
+    let a = IntervalSet::new([Interval(5, 9), Interval(0, 2), Interval(3, 3), Interval(7, 12)]);
+    assert_eq!(a.intervals(), &[Interval(0, 3), Interval(5, 12)]);
+    assert_eq!(a.cardinal(), 12);
+    assert!(a.contains(&3) && !a.contains(&4) && a.contains(&12));
+
+    let b = IntervalSet::new([Interval(2, 6), Interval(10, 20)]);
+    assert_eq!(a.union(&b).intervals(), &[Interval(0, 20)]);
+    assert_eq!(a.intersection(&b).intervals(),
+        &[Interval(2, 3), Interval(5, 6), Interval(10, 12)]);
+    assert_eq!(a.difference(&b).intervals(), &[Interval(0, 1), Interval(7, 9)]);
+    assert_eq!(a.complement(Interval(-5, 15)).intervals(),
+        &[Interval(-5, -1), Interval(4, 4), Interval(13, 15)]);
+    assert_eq!(a.shift(-10).intervals(), &[Interval(-10, -7), Interval(-5, 2)]);
+
+    let full = IntervalSet::from(Interval(i64::MIN, i64::MAX));
+    assert!(full.complement(Interval(i64::MIN, i64::MAX)).is_empty());
+    assert_eq!(IntervalSet::<u8>::default().complement(Interval(0, 255)).intervals(),
+        &[Interval(0, 255)]);
+}
+
+#[test]
+fn interval_split_partitions() {
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    let mut rng = StdRng::seed_from_u64(36);
+    for _ in 0..1000 {
+        let mut value = || rng.gen_range(-50i16..50);
+        let (a, b, x) = (value(), value(), value());
+        let interval = Interval::closed(a, b);
+        let (below, above) = interval.split_at(x);
+        let below = below.unwrap_or(Interval::empty());
+        let above = above.unwrap_or(Interval::empty());
+        for y in -60..60 {
+            assert_eq!(interval.contains(&y), below.contains(&y) || above.contains(&y),
+                "split of [{a}, {b}] at {x}, point {y}");
+            assert!(!(below.contains(&y) && above.contains(&y)),
+                "split of [{a}, {b}] at {x}, point {y}");
+            assert!(!below.contains(&y) || y < x, "split of [{a}, {b}] at {x}, point {y}");
+            assert!(!above.contains(&y) || y >= x, "split of [{a}, {b}] at {x}, point {y}");
+        }
+        assert_eq!(interval.cardinal(), below.cardinal() + above.cardinal(),
+            "split of [{a}, {b}] at {x}");
+    }
+
+    assert!(Interval::half_open(3u32, 3).is_empty());
+    assert!(Interval::half_open(0u32, 0).is_empty());
+    assert_eq!(Interval::half_open(3u32, 5), Interval(3, 4));
+    assert_eq!(Interval::closed(5, 3), Interval::<i32>::empty());
+    assert_eq!(Interval(5, 3), Interval::<i32>::empty());
+    assert_ne!(Interval(3, 5), Interval(3, 4));
+    assert_ne!(Interval(3, 3), Interval(5, 3));
+    let hash = |interval: Interval<i32>| {
+        use std::collections::hash_map::DefaultHasher;
+        let mut hasher = DefaultHasher::new();
+        interval.hash(&mut hasher);
+        hasher.finish()
+    };
+    assert_eq!(hash(Interval(5, 3)), hash(Interval::empty()));
+    assert_eq!(Interval::<u64>::empty().cardinal(), 0);
+}
@@ -0,0 +1,241 @@
+use num::{PrimInt, integer::gcd};
+use super::positions::*;
+
+// Closed polygon given by its successive vertices. Computations are done on
+// i128, which is exact for coordinates up to 2^62 in absolute value.
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub struct Polygon<T> (pub Vec<Position<T>>);
+
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation { Clockwise, CounterClockwise, Degenerate }
+
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location { Inside, Boundary, Outside }
+
+type Point = (i128, i128);
+
+fn point<T: PrimInt>(p: &Position<T>) -> Point {
+    (p.0.to_i128().unwrap(), p.1.to_i128().unwrap())
+}
+
+fn sub(p: Point, q: Point) -> Point {
+    (p.0 - q.0, p.1 - q.1)
+}
+
+fn cross(u: Point, v: Point) -> i128 {
+    u.0 * v.1 - u.1 * v.0
+}
+
+fn dot(u: Point, v: Point) -> i128 {
+    u.0 * v.0 + u.1 * v.1
+}
+
+// Whether p lies on the segment [a, b]
+fn on_segment(p: Point, (a, b): (Point, Point)) -> bool {
+    cross(sub(b, a), sub(p, a)) == 0 &&
+        a.0.min(b.0) <= p.0 && p.0 <= a.0.max(b.0) &&
+        a.1.min(b.1) <= p.1 && p.1 <= a.1.max(b.1)
+}
+
+fn segments_intersect((a, b): (Point, Point), (c, d): (Point, Point)) -> bool {
+    let d1 = cross(sub(b, a), sub(c, a)).signum();
+    let d2 = cross(sub(b, a), sub(d, a)).signum();
+    let d3 = cross(sub(d, c), sub(a, c)).signum();
+    let d4 = cross(sub(d, c), sub(b, c)).signum();
+    (d1 * d2 < 0 && d3 * d4 < 0) ||
+        on_segment(c, (a, b)) || on_segment(d, (a, b)) ||
+        on_segment(a, (c, d)) || on_segment(b, (c, d))
+}
+
+impl<T: PrimInt> Polygon<T> {
+    fn edges(&self) -> impl Iterator<Item = (Point, Point)> + '_ {
+        let next = self.0.iter().cycle().skip(1);
+        self.0.iter().zip(next).map(|(p, q)| (point(p), point(q)))
+    }
+
+    // Twice the signed area, by the shoelace formula; positive when the
+    // vertices turn counterclockwise, with the first coordinate as abscissa
+    pub fn double_signed_area(&self) -> i128 {
+        self.edges().map(|(p, q)| cross(p, q)).sum()
+    }
+
+    pub fn double_area(&self) -> u128 {
+        self.double_signed_area().unsigned_abs()
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        match self.double_signed_area().signum() {
+            1 => Orientation::CounterClockwise,
+            -1 => Orientation::Clockwise,
+            _ => Orientation::Degenerate
+        }
+    }
+
+    // Euclidean length of the edges
+    pub fn perimeter(&self) -> f64 {
+        self.edges()
+            .map(|(p, q)| {
+                let (dx, dy) = sub(q, p);
+                ((dx * dx + dy * dy) as f64).sqrt()
+            })
+            .sum()
+    }
+
+    // Number of lattice points lying on the edges
+    pub fn boundary_points(&self) -> u128 {
+        self.edges()
+            .map(|(p, q)| {
+                let (dx, dy) = sub(q, p);
+                gcd(dx, dy) as u128
+            })
+            .sum()
+    }
+
+    // Whether the polygon encloses nothing, in which case Pick's theorem
+    // doesn't apply
+    pub fn is_degenerate(&self) -> bool {
+        self.0.len() < 3 || self.double_signed_area() == 0
+    }
+
+    // Number of lattice points strictly inside, by Pick's theorem:
+    // A = I + B/2 - 1
+    pub fn interior_points(&self) -> u128 {
+        if self.is_degenerate() {
+            return 0
+        }
+        let double_area = self.double_area() as i128;
+        let boundary = self.boundary_points() as i128;
+        ((double_area - boundary + 2) / 2).max(0) as u128
+    }
+
+    // Interior and boundary lattice points, none for a degenerate polygon
+    pub fn lattice_points(&self) -> u128 {
+        if self.is_degenerate() {
+            return 0
+        }
+        self.interior_points() + self.boundary_points()
+    }
+
+    pub fn locate(&self, p: &Position<T>) -> Location {
+        let p = point(p);
+        if self.edges().any(|edge| on_segment(p, edge)) {
+            return Location::Boundary
+        }
+
+        // Counts crossings of the ray going from p towards increasing first
+        // coordinates, edges being half-open in the second coordinate
+        let mut inside = false;
+        for (a, b) in self.edges() {
+            if (a.1 > p.1) != (b.1 > p.1) {
+                let side = cross(sub(b, a), sub(p, a));
+                if (side > 0) == (b.1 > a.1) {
+                    inside = !inside;
+                }
+            }
+        }
+        if inside { Location::Inside } else { Location::Outside }
+    }
+
+    pub fn contains(&self, p: &Position<T>) -> bool {
+        self.locate(p) != Location::Outside
+    }
+
+    // The first pair of edges (by index, edge i going from vertex i to the
+    // next one) that touch other than at their shared vertex
+    pub fn self_intersection(&self) -> Option<(usize, usize)> {
+        let edges: Vec<(Point, Point)> = self.edges().collect();
+        let n = edges.len();
+        for i in 0..n {
+            for j in i + 1..n {
+                let intersect =
+                    if j == i + 1 || (i == 0 && j == n - 1) {
+                        // Adjacent edges only intersect if they fold back
+                        let (first, second) = if j == i + 1 { (i, j) } else { (j, i) };
+                        let (a, q) = edges[first];
+                        let (_, b) = edges[second];
+                        n <= 2 || (cross(sub(a, q), sub(b, q)) == 0 && dot(sub(a, q), sub(b, q)) > 0)
+                    }
+                    else {
+                        segments_intersect(edges[i], edges[j])
+                    };
+                if intersect {
+                    return Some ((i, j))
+                }
+            }
+        }
+        None
+    }
+
+    pub fn is_simple(&self) -> bool {
+        self.self_intersection().is_none()
+    }
+}
+
+#[test]
+fn polygon_lattice_points() {
+    let square = Polygon(vec![Position(0, 0), Position(0, 4), Position(4, 4), Position(4, 0)]);
+    assert_eq!(square.double_signed_area(), -32);
+    assert_eq!(square.orientation(), Orientation::Clockwise);
+    assert_eq!(square.perimeter(), 16.0);
+    assert_eq!(square.boundary_points(), 16);
+    assert_eq!(square.interior_points(), 9);
+    assert_eq!(square.lattice_points(), 25);
+
+    let triangle = Polygon(vec![Position(0u64, 0), Position(6, 0), Position(3, 6)]);
+    assert_eq!(triangle.orientation(), Orientation::CounterClockwise);
+    assert_eq!(triangle.double_area(), 36);
+    assert_eq!(triangle.boundary_points(), 12);
+    assert_eq!(triangle.interior_points(), 13);
+
+    let empty: Polygon<i32> = Polygon(vec![]);
+    assert_eq!((empty.interior_points(), empty.lattice_points()), (0, 0));
+    let point = Polygon(vec![Position(3, 3)]);
+    assert_eq!((point.interior_points(), point.lattice_points()), (0, 0));
+    let segment = Polygon(vec![Position(0, 0), Position(4, 0)]);
+    assert_eq!((segment.interior_points(), segment.lattice_points()), (0, 0));
+    let flat = Polygon(vec![Position(0, 0), Position(2, 0), Position(4, 0)]);
+    assert!(flat.is_degenerate());
+    assert_eq!((flat.interior_points(), flat.lattice_points()), (0, 0));
+
+    let far = Polygon(vec![Position(i64::MIN / 4, 0), Position(i64::MAX / 4, 0),
+        Position(0, i64::MAX / 4)]);
+    assert_eq!(far.orientation(), Orientation::CounterClockwise);
+}
+
+#[test]
+fn polygon_locate() {
+    // A U shape, whose notch is outside
+    let u = Polygon(vec![
+        Position(0, 0), Position(6, 0), Position(6, 6), Position(4, 6),
+        Position(4, 2), Position(2, 2), Position(2, 6), Position(0, 6)]);
+    assert!(u.is_simple());
+    assert_eq!(u.locate(&Position(1, 5)), Location::Inside);
+    assert_eq!(u.locate(&Position(3, 4)), Location::Outside);
+    assert_eq!(u.locate(&Position(3, 2)), Location::Boundary);
+    assert_eq!(u.locate(&Position(6, 3)), Location::Boundary);
+    assert_eq!(u.locate(&Position(7, 2)), Location::Outside);
+    assert_eq!(u.locate(&Position(-1, 6)), Location::Outside);
+
+    // The number of points found inside agrees with Pick's theorem
+    let mut inside = 0;
+    let mut boundary = 0;
+    for x in -1..8 {
+        for y in -1..8 {
+            match u.locate(&Position(x, y)) {
+                Location::Inside => inside += 1,
+                Location::Boundary => boundary += 1,
+                Location::Outside => ()
+            }
+        }
+    }
+    assert_eq!((inside, boundary), (u.interior_points(), u.boundary_points()));
+
+    let bowtie = Polygon(vec![Position(0, 0), Position(2, 2), Position(2, 0), Position(0, 2)]);
+    assert_eq!(bowtie.self_intersection(), Some((0, 2)));
+    let folded = Polygon(vec![Position(0, 0), Position(4, 0), Position(2, 0), Position(2, 2)]);
+    assert_eq!(folded.self_intersection(), Some((0, 1)));
+    let pinched = Polygon(vec![
+        Position(0, 0), Position(2, 0), Position(2, 2), Position(4, 2),
+        Position(4, 4), Position(2, 4), Position(2, 2), Position(0, 2)]);
+    assert!(!pinched.is_simple());
+}